@@ -2,22 +2,24 @@
 use arbitrary::{self, Arbitrary, Unstructured};
 use libfuzzer_sys::fuzz_target;
 use std::collections::HashMap;
-use urkel::{Database, Key};
+use urkel::{Database, Root};
 
 #[derive(Arbitrary, Clone, Debug)]
 enum Action {
     Flush,
     NewTx(TxId),
     NewTxAt(TxId, StateId),
-    TxAssertGet(TxId, Key, Value),
-    TxAssertShouldHave(TxId, Key),
-    TxSet(TxId, Key, Value),
-    TxRemove(TxId, Key, bool),
+    TxAssertGet(TxId, RawKey, Value),
+    TxAssertShouldHave(TxId, RawKey),
+    TxSet(TxId, RawKey, Value),
+    TxRemove(TxId, RawKey, bool),
     TxRevert(TxId, StateId),
     TxDrop(TxId),
     TxCommit(TxId, StateId),
 }
 
+type RawKey = [u8; 32];
+
 /// The state id is a simplification of state root.
 #[derive(Copy, Clone, Debug, Arbitrary, Hash, Eq, PartialEq)]
 struct StateId(usize);
@@ -32,9 +34,9 @@ struct Value(Vec<u8>);
 struct Scope {
     id: usize,
     active_txs: Vec<TxId>,
-    txs: HashMap<TxId, HashMap<Key, Value>>,
+    txs: HashMap<TxId, HashMap<RawKey, Value>>,
     states: Vec<StateId>,
-    state_kvs: HashMap<StateId, HashMap<Key, Value>>,
+    state_kvs: HashMap<StateId, HashMap<RawKey, Value>>,
 }
 
 impl Scope {
@@ -100,7 +102,7 @@ impl Arbitrary for Actions {
             if !scope.active_txs.is_empty() {
                 choices.push(|input, scope| {
                     let tx = *input.choose(&scope.active_txs)?;
-                    let key = Key::arbitrary(input)?;
+                    let key = RawKey::arbitrary(input)?;
                     let value = Value::arbitrary(input)?;
                     scope
                         .txs
@@ -146,7 +148,7 @@ impl Arbitrary for Actions {
                 // random remove
                 choices.push(|input, scope| {
                     let tx = *input.choose(&scope.active_txs)?;
-                    let key = Key::arbitrary(input)?;
+                    let key = RawKey::arbitrary(input)?;
                     let _ = scope.txs.get_mut(&tx).unwrap().remove(&key);
                     Ok(Action::TxRemove(tx, key, false))
                 });
@@ -194,7 +196,7 @@ fuzz_target!(|actions: Actions| {
     let prefix_dir = tempfile::tempdir().unwrap();
 
     let mut states_to_roots = HashMap::new();
-    states_to_roots.insert(StateId(0), [0; 32]);
+    states_to_roots.insert(StateId(0), Root::EMPTY);
 
     let mut actions = actions.actions.into_iter();
 
@@ -212,17 +214,20 @@ fuzz_target!(|actions: Actions| {
                     txs.insert(tx_id, db.new_tx_at(root).unwrap());
                 }
                 Action::TxSet(tx, k, v) => {
-                    txs.get_mut(&tx).unwrap().insert(&k, &v.0).unwrap();
+                    txs.get_mut(&tx)
+                        .unwrap()
+                        .insert(&urkel::Key::new(k), &v.0)
+                        .unwrap();
                 }
                 Action::TxAssertGet(tx, k, expected_value) => {
-                    let actual_value = txs.get_mut(&tx).unwrap().get(&k).unwrap();
+                    let actual_value = txs.get_mut(&tx).unwrap().get(&urkel::Key::new(k)).unwrap();
                     assert_eq!(Some(expected_value.0), actual_value);
                 }
                 Action::TxAssertShouldHave(tx, k) => {
-                    assert!(txs.get_mut(&tx).unwrap().has(&k).unwrap());
+                    assert!(txs.get_mut(&tx).unwrap().has(&urkel::Key::new(k)).unwrap());
                 }
                 Action::TxRemove(tx, k, must_exist) => {
-                    let r = txs.get_mut(&tx).unwrap().remove(&k);
+                    let r = txs.get_mut(&tx).unwrap().remove(&urkel::Key::new(k));
                     if must_exist {
                         assert!(r.is_ok());
                     }
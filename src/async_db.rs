@@ -0,0 +1,291 @@
+//! An async wrapper over the database for tokio applications.
+//!
+//! liburkel blocks on disk I/O, so every call is offloaded to the blocking pool of the runtime.
+
+use crate::{
+    ChangeKind, CommitReport, Database, Error, Key, MultiProof, Proof, Root, SavepointId,
+    Transaction, WriteBatch,
+};
+use futures_core::Stream;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task;
+
+/// The number of leaves an iteration stream buffers ahead of its consumer.
+const STREAM_BUFFER: usize = 64;
+
+/// Runs `f` on the blocking pool, propagating panics.
+async fn blocking<R: Send + 'static>(f: impl FnOnce() -> R + Send + 'static) -> R {
+    task::spawn_blocking(f)
+        .await
+        .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))
+}
+
+/// A database handle for async code.
+///
+/// The handle is cheap to clone and all clones share the same database. A call whose future is
+/// dropped still runs to completion on the blocking pool, only its result is discarded.
+#[derive(Clone, Debug)]
+pub struct AsyncDatabase {
+    db: Arc<Database>,
+}
+
+impl AsyncDatabase {
+    pub async fn open(prefix: impl AsRef<Path>) -> Result<Self, Error> {
+        let prefix = prefix.as_ref().to_path_buf();
+        let db = blocking(move || Database::open(prefix)).await?;
+        Ok(AsyncDatabase::from(db))
+    }
+
+    /// Returns the root hash of the tree at its current state.
+    pub fn root(&self) -> Root {
+        self.db.root()
+    }
+
+    /// Returns the value of `key` in the tree at `root`.
+    pub async fn get(&self, key: &Key, root: Root) -> Result<Option<Vec<u8>>, Error> {
+        let db = self.db.clone();
+        let key = *key;
+        blocking(move || db.snapshot(root)?.get(&key)).await
+    }
+
+    pub async fn prove(&self, key: &Key, root: Root) -> Result<Proof, Error> {
+        let db = self.db.clone();
+        let key = *key;
+        blocking(move || db.prove(&key, root)).await
+    }
+
+    pub async fn prove_many(&self, keys: &[Key], root: Root) -> Result<MultiProof, Error> {
+        let db = self.db.clone();
+        let keys = keys.to_vec();
+        blocking(move || db.prove_many(&keys, root)).await
+    }
+
+    /// Applies the batch on top of the current root and commits it. See [`Database::write`].
+    pub async fn write(&self, batch: WriteBatch) -> Result<Root, Error> {
+        let db = self.db.clone();
+        blocking(move || db.write(&batch)).await
+    }
+
+    /// Creates a transaction on top of the current root of the tree.
+    pub async fn new_tx(&self) -> Result<AsyncTransaction, Error> {
+        AsyncTransaction::spawn(self.db.clone(), None).await
+    }
+
+    /// Creates a transaction on top of the given committed `root`.
+    pub async fn new_tx_at(&self, root: Root) -> Result<AsyncTransaction, Error> {
+        AsyncTransaction::spawn(self.db.clone(), Some(root)).await
+    }
+
+    /// Returns a stream over the leaves of the tree at `root`, in the order of their keys.
+    ///
+    /// The iteration runs on the blocking pool and stops once the stream is dropped.
+    pub fn iter(&self, root: Root) -> AsyncIter<'static> {
+        let db = self.db.clone();
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        task::spawn_blocking(move || match db.iter(root) {
+            Ok(iter) => forward(iter, &sender),
+            Err(err) => {
+                let _ = sender.blocking_send(Err(err));
+            }
+        });
+        AsyncIter::new(receiver)
+    }
+}
+
+impl From<Database> for AsyncDatabase {
+    fn from(db: Database) -> Self {
+        AsyncDatabase { db: Arc::new(db) }
+    }
+}
+
+type Command = Box<dyn for<'a> FnOnce(&mut Transaction<'a>) + Send>;
+
+/// A transaction for async code.
+///
+/// The underlying [`Transaction`] lives on a worker in the blocking pool and carries out calls
+/// one by one, in the order they were made. A call is handed to the worker as a whole on the
+/// first poll of its future, so dropping the future never interrupts it halfway: the call still
+/// runs to completion and only its result is discarded. The transaction is destroyed by the
+/// worker once the `AsyncTransaction` is dropped and the calls in flight are done.
+///
+/// The worker occupies a thread of the blocking pool for as long as the transaction is alive.
+#[derive(Debug)]
+pub struct AsyncTransaction {
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl AsyncTransaction {
+    async fn spawn(db: Arc<Database>, root: Option<Root>) -> Result<Self, Error> {
+        let (commands, mut receiver) = mpsc::unbounded_channel::<Command>();
+        let (ready, created) = oneshot::channel();
+        task::spawn_blocking(move || {
+            let tx = match root {
+                Some(root) => db.new_tx_at(root),
+                None => db.new_tx(),
+            };
+            let mut tx = match tx {
+                Ok(tx) => tx,
+                Err(err) => {
+                    let _ = ready.send(Err(err));
+                    return;
+                }
+            };
+            let _ = ready.send(Ok(()));
+            while let Some(command) = receiver.blocking_recv() {
+                command(&mut tx);
+            }
+        });
+        created.await.expect("the transaction worker panicked")?;
+        Ok(AsyncTransaction { commands })
+    }
+
+    /// Runs `f` on the worker and returns its result.
+    pub(crate) async fn call<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut Transaction<'_>) -> R + Send + 'static,
+    ) -> R {
+        let (reply, result) = oneshot::channel();
+        let _ = self
+            .commands
+            .send(Box::new(move |tx: &mut Transaction<'_>| {
+                let _ = reply.send(f(tx));
+            }));
+        result.await.expect("the transaction worker panicked")
+    }
+
+    pub async fn root(&self) -> Root {
+        self.call(|tx| tx.root()).await
+    }
+
+    pub async fn get(&self, key: &Key) -> Result<Option<Vec<u8>>, Error> {
+        let key = *key;
+        self.call(move |tx| tx.get(&key)).await
+    }
+
+    pub async fn has(&self, key: &Key) -> Result<bool, Error> {
+        let key = *key;
+        self.call(move |tx| tx.has(&key)).await
+    }
+
+    pub async fn insert(&mut self, key: &Key, value: impl Into<Vec<u8>>) -> Result<(), Error> {
+        let key = *key;
+        let value = value.into();
+        self.call(move |tx| tx.insert(&key, &value)).await
+    }
+
+    pub async fn remove(&mut self, key: &Key) -> Result<(), Error> {
+        let key = *key;
+        self.call(move |tx| tx.remove(&key)).await
+    }
+
+    pub async fn prove(&self, key: &Key) -> Result<Proof, Error> {
+        let key = *key;
+        self.call(move |tx| tx.prove(&key)).await
+    }
+
+    pub async fn prove_many(&self, keys: &[Key]) -> Result<MultiProof, Error> {
+        let keys = keys.to_vec();
+        self.call(move |tx| tx.prove_many(&keys)).await
+    }
+
+    /// See [`Transaction::revert`].
+    pub async fn revert(&mut self, root: Root) -> Result<(), Error> {
+        self.call(move |tx| tx.revert(root)).await
+    }
+
+    /// See [`Transaction::apply`].
+    pub async fn apply(&mut self, batch: WriteBatch) -> Result<(), Error> {
+        self.call(move |tx| tx.apply(&batch)).await
+    }
+
+    /// See [`Transaction::savepoint`].
+    pub async fn savepoint(&mut self) -> SavepointId {
+        self.call(|tx| tx.savepoint()).await
+    }
+
+    /// See [`Transaction::rollback_to`].
+    pub async fn rollback_to(&mut self, savepoint: SavepointId) -> Result<(), Error> {
+        self.call(move |tx| tx.rollback_to(savepoint)).await
+    }
+
+    /// See [`Transaction::release`].
+    pub async fn release(&mut self, savepoint: SavepointId) -> Result<(), Error> {
+        self.call(move |tx| tx.release(savepoint)).await
+    }
+
+    /// See [`Transaction::clear`].
+    pub async fn clear(&mut self) -> Result<(), Error> {
+        self.call(|tx| tx.clear()).await
+    }
+
+    /// See [`Transaction::commit`].
+    pub async fn commit(&mut self) -> Result<CommitReport, Error> {
+        self.call(|tx| tx.commit()).await
+    }
+
+    pub async fn is_dirty(&self) -> bool {
+        self.call(|tx| tx.is_dirty()).await
+    }
+
+    /// See [`Transaction::pending_changes`].
+    pub async fn pending_changes(&self) -> Result<Vec<(Key, ChangeKind)>, Error> {
+        self.call(|tx| tx.pending_changes()).await
+    }
+
+    /// Returns a stream over the leaves of the transaction, in the order of their keys.
+    ///
+    /// The worker is busy with the iteration until the stream ends or is dropped, so the stream
+    /// borrows the transaction mutably.
+    pub fn iter(&mut self) -> AsyncIter<'_> {
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        let _ = self
+            .commands
+            .send(Box::new(move |tx: &mut Transaction<'_>| match tx.iter() {
+                Ok(iter) => forward(iter, &sender),
+                Err(err) => {
+                    let _ = sender.blocking_send(Err(err));
+                }
+            }));
+        AsyncIter::new(receiver)
+    }
+}
+
+/// Sends the leaves to the stream until the iterator ends or the stream is dropped.
+fn forward(iter: crate::Iter, sender: &mpsc::Sender<Result<(Key, Vec<u8>), Error>>) {
+    for leaf in iter {
+        if sender.blocking_send(leaf).is_err() {
+            break;
+        }
+    }
+}
+
+/// A stream over the leaves of a tree.
+///
+/// Created by [`AsyncDatabase::iter`] and [`AsyncTransaction::iter`].
+#[derive(Debug)]
+pub struct AsyncIter<'a> {
+    leaves: mpsc::Receiver<Result<(Key, Vec<u8>), Error>>,
+    _tx: PhantomData<&'a mut AsyncTransaction>,
+}
+
+impl AsyncIter<'_> {
+    fn new(leaves: mpsc::Receiver<Result<(Key, Vec<u8>), Error>>) -> Self {
+        AsyncIter {
+            leaves,
+            _tx: PhantomData,
+        }
+    }
+}
+
+impl Stream for AsyncIter<'_> {
+    type Item = Result<(Key, Vec<u8>), Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.leaves.poll_recv(cx)
+    }
+}
@@ -0,0 +1,80 @@
+use crate::error::Error;
+use crate::{Key, MAX_VALUE_SIZE};
+
+/// A single operation of a [`WriteBatch`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BatchOp {
+    Put(Key, Vec<u8>),
+    Delete(Key),
+}
+
+/// A list of writes that are applied atomically with [`Transaction::apply`] or
+/// [`Database::write`].
+///
+/// [`Transaction::apply`]: crate::Transaction::apply
+/// [`Database::write`]: crate::Database::write
+///
+/// ```
+/// # use urkel::{Database, Key, WriteBatch};
+/// # let prefix_dir = tempfile::tempdir().unwrap();
+/// # let db = Database::open(prefix_dir.path()).unwrap();
+/// let mut batch = WriteBatch::new();
+/// batch
+///     .put(&Key::new([1; 32]), b"one")
+///     .put(&Key::new([2; 32]), b"two")
+///     .delete(&Key::new([1; 32]));
+/// let root = db.write(&batch).unwrap();
+/// assert_eq!(root, db.root());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WriteBatch {
+    ops: Vec<BatchOp>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues an insertion of `value` under `key`.
+    pub fn put(&mut self, key: &Key, value: impl Into<Vec<u8>>) -> &mut Self {
+        self.ops.push(BatchOp::Put(*key, value.into()));
+        self
+    }
+
+    /// Queues a removal of `key`. Applying the batch fails if the key doesn't exist by then.
+    pub fn delete(&mut self, key: &Key) -> &mut Self {
+        self.ops.push(BatchOp::Delete(*key));
+        self
+    }
+
+    pub fn ops(&self) -> &[BatchOp] {
+        &self.ops
+    }
+
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.ops.clear();
+    }
+
+    /// Checks the operations that can be checked without looking at the tree.
+    ///
+    /// Keys are always 32 bytes long by construction, so only the values are checked.
+    pub(crate) fn validate(&self) -> Result<(), Error> {
+        for op in &self.ops {
+            if let BatchOp::Put(_, value) = op {
+                if value.len() > MAX_VALUE_SIZE {
+                    return Err(Error::ValueTooLarge);
+                }
+            }
+        }
+        Ok(())
+    }
+}
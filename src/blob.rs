@@ -0,0 +1,147 @@
+use crate::util::{blake2b_256, parse_hex_32, Hex};
+use crate::{Database, Error, Root};
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The name of the blob directory in the prefix directory.
+pub(crate) const BLOBS_DIR: &str = "blobs";
+
+/// Numbers the temporary files of the blobs being written by this process.
+static TMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// A content-addressed store of blobs too large for the tree, kept next to it under the
+/// prefix.
+///
+/// Each blob is stored in its own file named after its BLAKE2b-256 hash, and the tree only
+/// holds the hash. A proof of a key thus commits to the blob stored under it. Blobs are written
+/// with [`Transaction::put_blob`] and read with [`Transaction::get_blob`], which checks the
+/// hash. Nothing is created on disk until the first blob is written.
+///
+/// Blobs are never deleted along with the keys that refer to them, since older roots may still
+/// reach them. [`Database::sweep_blobs`] deletes the blobs that are no longer needed.
+///
+/// [`Transaction::put_blob`]: crate::Transaction::put_blob
+/// [`Transaction::get_blob`]: crate::Transaction::get_blob
+#[derive(Debug)]
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    pub(crate) fn new(prefix: &Path) -> Self {
+        BlobStore {
+            dir: prefix.join(BLOBS_DIR),
+        }
+    }
+
+    fn path(&self, hash: &[u8; 32]) -> PathBuf {
+        self.dir.join(Hex(hash).to_string())
+    }
+
+    /// Returns the blob with the given hash, or `None` if there is no such blob.
+    ///
+    /// Fails with `CorruptBlob` if the content of the blob doesn't match its hash.
+    pub fn get(&self, hash: &[u8; 32]) -> Result<Option<Vec<u8>>, Error> {
+        let path = self.path(hash);
+        let blob = match fs::read(&path) {
+            Ok(blob) => blob,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => {
+                return Err(Error::Io {
+                    op: "get_blob",
+                    source,
+                })
+            }
+        };
+        if blake2b_256(&blob) != *hash {
+            return Err(Error::CorruptBlob { path });
+        }
+        Ok(Some(blob))
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.path(hash).is_file()
+    }
+
+    /// Writes the blob unless it's already stored and returns its hash.
+    ///
+    /// The blob is written to a temporary file that is renamed into place once it's synced, so
+    /// a crash never leaves a truncated blob behind. The temporary file is named after the
+    /// process and a per-process counter, so writers storing the same blob concurrently never
+    /// share one.
+    pub(crate) fn put(&self, blob: &[u8]) -> Result<[u8; 32], Error> {
+        let hash = blake2b_256(blob);
+        let path = self.path(&hash);
+        if path.is_file() {
+            return Ok(hash);
+        }
+        let tmp_path = self.dir.join(format!(
+            "{}.{}.{}.tmp",
+            Hex(&hash),
+            process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let io_error = |source| Error::Io {
+            op: "put_blob",
+            source,
+        };
+        fs::create_dir_all(&self.dir).map_err(io_error)?;
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp_path)
+            .map_err(io_error)?;
+        file.write_all(blob).map_err(io_error)?;
+        file.sync_all().map_err(io_error)?;
+        fs::rename(&tmp_path, &path).map_err(io_error)?;
+        Ok(hash)
+    }
+
+    /// Deletes every blob that isn't referenced by a leaf of one of the `retain` roots, along
+    /// with the temporary files of interrupted writes. Returns the number of deleted blobs.
+    ///
+    /// Any leaf whose value is the hash of a stored blob counts as a reference to it.
+    pub(crate) fn sweep(&self, db: &Database, retain: &[Root]) -> Result<usize, Error> {
+        let io_error = |source| Error::Io {
+            op: "sweep_blobs",
+            source,
+        };
+        let entries = match self.dir.read_dir() {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(io_error(err)),
+        };
+
+        let mut referenced = BTreeSet::new();
+        for root in retain {
+            for leaf in db.iter(*root)? {
+                let (_, value) = leaf?;
+                if value.len() == 32 {
+                    let mut hash = [0; 32];
+                    hash.copy_from_slice(&value);
+                    referenced.insert(hash);
+                }
+            }
+        }
+
+        let mut removed = 0;
+        for entry in entries {
+            let path = entry.map_err(io_error)?.path();
+            let hash = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| parse_hex_32(name).ok());
+            if !hash.is_some_and(|hash| referenced.contains(&hash)) {
+                fs::remove_file(&path).map_err(io_error)?;
+                if hash.is_some() {
+                    removed += 1;
+                }
+            }
+        }
+        Ok(removed)
+    }
+}
@@ -0,0 +1,156 @@
+use crate::blob::BLOBS_DIR;
+use crate::diff::Change;
+use crate::lock::{PrefixLock, COMMIT_LOCK_FILE, LOCK_FILE};
+use crate::{Database, Error, Root};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// The directory in the prefix directory where the compacted store is built.
+pub(crate) const COMPACT_DIR: &str = "compact";
+
+/// The file that marks the compacted store as complete. It lists the files of the store.
+const COMPLETE_FILE: &str = "COMPLETE";
+
+/// Describes what a call to [`Database::compact`] has done.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompactReport {
+    /// The roots that were kept, the current root last.
+    pub retained: Vec<Root>,
+    /// The size of the store in bytes before the compaction.
+    pub size_before: u64,
+    /// The size of the store in bytes after the compaction.
+    pub size_after: u64,
+}
+
+impl CompactReport {
+    /// Returns the number of bytes freed by the compaction.
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.size_before.saturating_sub(self.size_after)
+    }
+}
+
+/// Returns `true` if the entry of the prefix directory belongs to liburkel, as opposed to the
+/// files this crate keeps next to the store.
+pub(crate) fn is_store_entry(name: &OsStr) -> bool {
+    name != LOCK_FILE && name != COMMIT_LOCK_FILE && name != BLOBS_DIR && name != COMPACT_DIR
+}
+
+/// Returns the total size of the files of the store at `prefix`.
+pub(crate) fn store_size(prefix: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in prefix.read_dir()? {
+        let entry = entry?;
+        if is_store_entry(&entry.file_name()) {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+/// Writes a new store into the compaction directory of `db`, holding only the nodes reachable
+/// from `roots`, and marks it as complete.
+///
+/// The roots are committed in order, each one as a transaction on top of the previous one, so
+/// the last root becomes the head of the new store. Fails with `RootMismatch`, leaving the new
+/// store incomplete, if a root can't be reproduced.
+pub(crate) fn rebuild(db: &Database, prefix: &Path, roots: &[Root]) -> Result<(), Error> {
+    let dir = prefix.join(COMPACT_DIR);
+    let io_error = |source| Error::Io {
+        op: "compact",
+        source,
+    };
+    // A leftover of an interrupted compaction is incomplete, or it would have been rolled
+    // forward on open.
+    remove_dir(&dir).map_err(io_error)?;
+
+    let lock = PrefixLock::acquire(&dir, "compact")?;
+    {
+        let compacted = Database::open_store(&dir, Some(lock))?;
+        let mut tx = compacted.new_tx()?;
+        let mut prev = Root::EMPTY;
+        for root in roots {
+            for change in db.diff(prev, *root)? {
+                match change? {
+                    Change::Added(key, value) | Change::Changed(key, _, value) => {
+                        tx.insert(&key, &value)?
+                    }
+                    Change::Removed(key, _) => tx.remove(&key)?,
+                }
+            }
+            tx.commit()?;
+            // The new store must not replace the old one unless it holds the same roots.
+            if tx.root() != *root {
+                return Err(Error::RootMismatch {
+                    expected: *root,
+                    actual: tx.root(),
+                });
+            }
+            prev = *root;
+        }
+    }
+
+    let mut files = String::new();
+    for entry in dir.read_dir().map_err(io_error)? {
+        let name = entry.map_err(io_error)?.file_name();
+        if is_store_entry(&name) {
+            let name = name.to_str().ok_or(Error::PathErr)?;
+            files.push_str(name);
+            files.push('\n');
+        }
+    }
+    let tmp_path = dir.join(COMPLETE_FILE).with_extension("tmp");
+    let mut file = File::create(&tmp_path).map_err(io_error)?;
+    file.write_all(files.as_bytes()).map_err(io_error)?;
+    file.sync_all().map_err(io_error)?;
+    fs::rename(&tmp_path, dir.join(COMPLETE_FILE)).map_err(io_error)?;
+    File::open(&dir)
+        .and_then(|dir| dir.sync_all())
+        .map_err(io_error)
+}
+
+/// Finishes or discards an interrupted compaction of the store at `prefix`.
+///
+/// A complete compacted store replaces the files of the store, otherwise it is deleted. Every
+/// step can be repeated, so this can be called again if the process is killed while it runs.
+pub(crate) fn recover(prefix: &Path) -> Result<(), Error> {
+    let dir = prefix.join(COMPACT_DIR);
+    let io_error = |source| Error::Io {
+        op: "compact",
+        source,
+    };
+    let files = match fs::read_to_string(dir.join(COMPLETE_FILE)) {
+        Ok(files) => files,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return remove_dir(&dir).map_err(io_error)
+        }
+        Err(err) => return Err(io_error(err)),
+    };
+    let files = files.lines().collect::<Vec<_>>();
+
+    for entry in prefix.read_dir().map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        let name = entry.file_name();
+        if is_store_entry(&name) && !files.iter().any(|file| OsStr::new(file) == name) {
+            fs::remove_file(entry.path()).map_err(io_error)?;
+        }
+    }
+    for file in files {
+        match fs::rename(dir.join(file), prefix.join(file)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(io_error(err)),
+            _ => {}
+        }
+    }
+    File::open(prefix)
+        .and_then(|prefix| prefix.sync_all())
+        .map_err(io_error)?;
+    remove_dir(&dir).map_err(io_error)
+}
+
+fn remove_dir(dir: &Path) -> io::Result<()> {
+    match fs::remove_dir_all(dir) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
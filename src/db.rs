@@ -1,67 +1,224 @@
+use crate::batch::{BatchOp, WriteBatch};
+use crate::blob::{BlobStore, BLOBS_DIR};
+use crate::compact::{self, CompactReport, COMPACT_DIR};
+use crate::diff::{Diff, DiffLeaves};
 use crate::error::{Errno, Error};
+use crate::iter::Iter;
+use crate::lock::{CommitLock, PrefixLock, COMMIT_LOCK_FILE, LOCK_FILE};
+use crate::multiproof::MultiProof;
+use crate::options::DatabaseOptions;
 use crate::proof::Proof;
-use std::{marker::PhantomData, path::Path, ptr};
+use crate::{Key, Root, MAX_VALUE_SIZE};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::{Mutex, PoisonError};
 use urkel_sys as sys;
 
-pub const MAX_VALUE_SIZE: usize = 1024;
-pub type Key = [u8; 32];
-
 #[derive(Debug)]
 pub struct Database {
     tree: *mut sys::urkel_t,
+    prefix: PathBuf,
+    /// The lock on the prefix, held by every handle that isn't read-only.
+    lock: Option<PrefixLock>,
+    commit_lock: CommitLock,
+    blobs: BlobStore,
+    /// The number of roots to keep, see [`DatabaseOptions::retention_limit`].
+    retention_limit: Option<usize>,
+    /// The last roots committed since the database was opened or last compacted, oldest first.
+    /// Only recorded if there is a retention policy, and at most `history_cap` of them.
+    history: Mutex<Vec<Root>>,
 }
 
-// urkel provides inherent thread-safety
+// liburkel guards the store and the tree head with a lock, so the tree can be shared between
+// threads. The methods that replace the head without a transaction take `&mut self`.
 unsafe impl Send for Database {}
 unsafe impl Sync for Database {}
 
 impl Database {
+    /// Opens the store at `prefix`, creating it if it doesn't exist.
+    ///
+    /// Same as `DatabaseOptions::new().open(prefix)`, see [`DatabaseOptions`] for more control.
     pub fn open(prefix: impl AsRef<Path>) -> Result<Self, Error> {
-        let prefix = crate::util::path_into_c_string(prefix.as_ref())?;
-        let tree = unsafe { sys::urkel_open(prefix.as_ptr()) };
-        if tree.is_null() {
-            return Err(Errno::fetch().into_error());
+        DatabaseOptions::new().open(prefix)
+    }
+
+    /// Opens the store at `prefix` with liburkel, creating it if it doesn't exist.
+    ///
+    /// The handle is read-only unless it holds the lock on the prefix.
+    pub(crate) fn open_store(prefix: &Path, lock: Option<PrefixLock>) -> Result<Self, Error> {
+        let commit_lock = CommitLock::open(prefix, "open")?;
+        let tree = {
+            let _commit = commit_lock.lock(lock.is_some(), "open")?;
+            // A read-only handle doesn't hold the lock on the prefix, so it can't finish an
+            // interrupted compaction.
+            if lock.is_some() {
+                compact::recover(prefix)?;
+            }
+            open_tree(prefix, "open")?
+        };
+        Ok(Database {
+            tree,
+            prefix: prefix.to_path_buf(),
+            lock,
+            commit_lock,
+            blobs: BlobStore::new(prefix),
+            retention_limit: None,
+            history: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub(crate) fn set_retention_limit(&mut self, retention_limit: Option<usize>) {
+        self.retention_limit = retention_limit;
+        let root = self.root();
+        let history = self
+            .history
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        history.clear();
+        if retention_limit.is_some() {
+            history.push(root);
         }
-        Ok(Database { tree })
     }
 
+    /// Opens the existing store at `prefix` for reading only.
+    ///
+    /// Every write through the returned handle fails with `ReadOnly`: inserting into or
+    /// removing from the tree, moving the head and committing transactions. Transactions can
+    /// still be created and modified in memory, e.g. to compute a root or a proof.
+    ///
+    /// The handle doesn't take the lock on the prefix, so the store can be opened this way
+    /// while another handle, in this or another process, writes to it. The handle sees the
+    /// roots committed up to the moment it was opened, call [`Database::refresh`] to follow
+    /// the writer.
+    ///
+    /// liburkel has no read-only mode of its own, so the store is opened as usual and only the
+    /// writes through the handle are refused by this crate. Since liburkel repairs a store
+    /// whose last write is incomplete when it opens it, opening waits for a commit of the
+    /// writer in progress to finish and holds off the next one until the store is open. The
+    /// store is thus always found consistent, and only a write cut short by a crashed writer
+    /// is ever repaired.
+    ///
+    /// Same as `DatabaseOptions::new().read_only(true).open(prefix)`.
+    pub fn open_read_only(prefix: impl AsRef<Path>) -> Result<Self, Error> {
+        DatabaseOptions::new().read_only(true).open(prefix)
+    }
+
+    /// Returns the handle on the tree.
+    ///
+    /// Panics if the store was left closed by a failed [compaction](Database::compact).
+    fn tree(&self) -> *mut sys::urkel_t {
+        assert!(
+            !self.tree.is_null(),
+            "the store was left closed by a failed compaction, refresh the database"
+        );
+        self.tree
+    }
+
+    /// Returns `true` if the database was opened with [`Database::open_read_only`].
+    pub fn is_read_only(&self) -> bool {
+        self.lock.is_none()
+    }
+
+    /// Fails with `ReadOnly` if the database was opened read-only.
+    pub(crate) fn check_writable(&self) -> Result<(), Error> {
+        if self.is_read_only() {
+            return Err(Error::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Reopens the store to pick up the roots committed by other processes since it was opened,
+    /// and returns the current root.
+    ///
+    /// Like opening, reopening waits for a commit in progress to finish. A compaction by the
+    /// writer replaces the files of the store, so reads through a read-only handle may fail
+    /// after one until the handle is refreshed.
+    ///
+    /// Takes `&mut self` since none of the transactions, snapshots and iterators of the
+    /// database may be alive while the store is reopened. The database stays usable at its
+    /// previous state if reopening fails. This also reopens a store left closed by a failed
+    /// [compaction](Database::compact).
+    pub fn refresh(&mut self) -> Result<Root, Error> {
+        let tree = {
+            let _commit = self.commit_lock.lock(!self.is_read_only(), "refresh")?;
+            open_tree(&self.prefix, "refresh")?
+        };
+        let old = std::mem::replace(&mut self.tree, tree);
+        if !old.is_null() {
+            unsafe {
+                sys::urkel_close(old);
+            }
+        }
+        Ok(self.root())
+    }
+
+    /// Deletes the store at `prefix`.
+    ///
+    /// Fails with `Locked` if the store is open for writing.
     pub fn destroy(prefix: impl AsRef<Path>) -> Result<(), Error> {
-        let prefix = crate::util::path_into_c_string(prefix.as_ref())?;
-        let ret = unsafe { sys::urkel_destroy(prefix.as_ptr()) };
+        let prefix = prefix.as_ref();
+        let c_prefix = crate::util::path_into_c_string(prefix)?;
+        // The lock is held until the store is gone. The lock files, the blobs and a leftover
+        // compaction are removed beforehand, since liburkel only deletes the directory if it
+        // holds nothing but the files of the store.
+        let _lock = if prefix.is_dir() {
+            let lock = PrefixLock::acquire(prefix, "destroy")?;
+            let blobs = std::fs::remove_dir_all(prefix.join(BLOBS_DIR));
+            let compacted = std::fs::remove_dir_all(prefix.join(COMPACT_DIR));
+            let commit_lock_file = std::fs::remove_file(prefix.join(COMMIT_LOCK_FILE));
+            let lock_file = std::fs::remove_file(prefix.join(LOCK_FILE));
+            for result in [blobs, compacted, commit_lock_file, lock_file] {
+                match result {
+                    Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(Error::Io {
+                            op: "destroy",
+                            source: err,
+                        })
+                    }
+                    _ => {}
+                }
+            }
+            Some(lock)
+        } else {
+            None
+        };
+        let ret = unsafe { sys::urkel_destroy(c_prefix.as_ptr()) };
         if ret == 0 {
-            return Err(Errno::fetch().into_error());
+            return Err(Errno::fetch().into_error("destroy", prefix));
         }
         Ok(())
     }
 
-    pub fn new_tx(&self) -> Result<Transaction, Error> {
-        let tx = unsafe { sys::urkel_tx_create(self.tree, ptr::null()) };
+    /// Creates a transaction on top of the current root of the tree.
+    ///
+    /// The transaction borrows the database and thus cannot outlive it.
+    pub fn new_tx(&self) -> Result<Transaction<'_>, Error> {
+        let tx = unsafe { sys::urkel_tx_create(self.tree(), ptr::null()) };
         if tx.is_null() {
-            return Err(Errno::fetch().into_error());
+            return Err(Errno::fetch().into_error("new_tx", &self.prefix));
         }
-        Ok(Transaction {
-            tx,
-            _marker: PhantomData,
-        })
+        Ok(Transaction::new(self, tx))
     }
 
-    pub fn new_tx_at(&self, root: [u8; 32]) -> Result<Transaction, Error> {
-        let tx = unsafe { sys::urkel_tx_create(self.tree, root.as_ptr()) };
+    /// Creates a transaction on top of the given committed `root`.
+    ///
+    /// The transaction borrows the database and thus cannot outlive it.
+    pub fn new_tx_at(&self, root: Root) -> Result<Transaction<'_>, Error> {
+        let tx = unsafe { sys::urkel_tx_create(self.tree(), root.as_ptr()) };
         if tx.is_null() {
-            return Err(Errno::fetch().into_error());
+            return Err(Errno::fetch().into_error("new_tx", &self.prefix));
         }
-        Ok(Transaction {
-            tx,
-            _marker: PhantomData,
-        })
+        Ok(Transaction::new(self, tx))
     }
 
-    pub fn prove(&self, key: &Key, root: [u8; 32]) -> Result<Proof, Error> {
+    pub fn prove(&self, key: &Key, root: Root) -> Result<Proof, Error> {
         let mut proof_raw = ptr::null_mut();
         let mut proof_len = 0usize;
         let ret = unsafe {
             sys::urkel_prove(
-                self.tree,
+                self.tree(),
                 &mut proof_raw as *mut *mut _,
                 &mut proof_len as *mut usize,
                 key.as_ptr(),
@@ -71,7 +228,7 @@ impl Database {
         if ret == 0 {
             // we assume that the buf wasn't allocated.
             debug_assert_eq!(proof_raw, ptr::null_mut());
-            return Err(Errno::fetch().into_error());
+            return Err(Errno::fetch().into_error("prove", &self.prefix));
         }
 
         let proof = unsafe {
@@ -82,81 +239,500 @@ impl Database {
         Ok(proof)
     }
 
+    /// Proves the presence or absence of each of `keys` in the tree at `root`.
+    ///
+    /// Nodes shared between the paths of the keys are included only once.
+    pub fn prove_many(&self, keys: &[Key], root: Root) -> Result<MultiProof, Error> {
+        let proofs = keys
+            .iter()
+            .map(|key| self.prove(key, root))
+            .collect::<Result<_, _>>()?;
+        Ok(MultiProof::from_proofs(proofs))
+    }
+
     /// Returns the root hash of the tree at its current state.
     ///
     /// The root of a freshly created database is all zeroes.
     ///
     /// ```
-    /// # use urkel::Database;
+    /// # use urkel::{Database, Root};
     /// # let prefix_dir = tempfile::tempdir().unwrap();
     /// # let prefix = prefix_dir.path().display().to_string();
     /// let db = Database::open(&prefix).unwrap();
-    /// assert_eq!(db.root(), [0; 32]);
+    /// assert_eq!(db.root(), Root::EMPTY);
     /// ```
-    pub fn root(&self) -> [u8; 32] {
-        let mut root = [0; 32];
-        unsafe { sys::urkel_root(self.tree, root.as_mut_ptr()) }
+    pub fn root(&self) -> Root {
+        let mut root = Root::EMPTY;
+        unsafe { sys::urkel_root(self.tree(), root.as_mut_ptr()) }
         root
     }
 
-    pub fn iter(&self, at: [u8; 32]) -> Result<Iter, Error> {
-        let iter = unsafe { sys::urkel_iterate(self.tree, at.as_ptr()) };
+    /// Iterates over the leaves of the tree at the given committed root.
+    ///
+    /// The iterator borrows the database and thus cannot outlive it.
+    pub fn iter(&self, at: Root) -> Result<Iter<'_>, Error> {
+        let iter = unsafe { sys::urkel_iterate(self.tree(), at.as_ptr()) };
         if iter.is_null() {
-            return Err(Errno::fetch().into_error());
+            return Err(Errno::fetch().into_error("iter", &self.prefix));
+        }
+        Ok(Iter::new(iter, &self.prefix))
+    }
+
+    /// Inserts a value directly into the tree and persists the resulting root as the new head.
+    ///
+    /// Doesn't support values more than 1024 bytes long.
+    pub fn insert(&mut self, key: &Key, value: &[u8]) -> Result<(), Error> {
+        self.check_writable()?;
+        if value.len() > MAX_VALUE_SIZE {
+            return Err(Error::ValueTooLarge);
+        }
+        let ret =
+            unsafe { sys::urkel_insert(self.tree(), key.as_ptr(), value.as_ptr(), value.len()) };
+        if ret == 0 {
+            return Err(Errno::fetch().into_error("insert", &self.prefix));
+        }
+        self.write_head("insert")?;
+        self.apply_retention().map(drop)
+    }
+
+    /// Removes a value directly from the tree and persists the resulting root as the new head.
+    pub fn remove(&mut self, key: &Key) -> Result<(), Error> {
+        self.check_writable()?;
+        let ret = unsafe { sys::urkel_remove(self.tree(), key.as_ptr()) };
+        if ret == 0 {
+            return Err(Errno::fetch().into_error("remove", &self.prefix));
+        }
+        self.write_head("remove")?;
+        self.apply_retention().map(drop)
+    }
+
+    /// Rewinds the head of the tree to an earlier committed `root` and persists it, so that
+    /// the database reopens at that root.
+    ///
+    /// Returns `NotFound` if the root is unknown to this database.
+    pub fn set_head(&mut self, root: Root) -> Result<(), Error> {
+        self.check_writable()?;
+        let ret = unsafe { sys::urkel_inject(self.tree(), root.as_ptr()) };
+        if ret == 0 {
+            return Err(Errno::fetch().into_error("set_head", &self.prefix));
+        }
+        self.write_head("set_head")?;
+        self.apply_retention().map(drop)
+    }
+
+    /// Writes out the in-memory head of the tree and records it as the persisted root.
+    ///
+    /// The tree-level functions only update the root held in memory. Committing a transaction
+    /// created on top of it flushes the nodes to the store and writes the meta root.
+    fn write_head(&mut self, op: &'static str) -> Result<(), Error> {
+        let _commit = self.commit_lock.lock(true, op)?;
+        let tx = unsafe { sys::urkel_tx_create(self.tree(), ptr::null()) };
+        if tx.is_null() {
+            return Err(Errno::fetch().into_error(op, &self.prefix));
+        }
+        let ret = unsafe { sys::urkel_tx_commit(tx) };
+        let result = if ret == 0 {
+            Err(Errno::fetch().into_error(op, &self.prefix))
+        } else {
+            self.record_commit(self.root());
+            Ok(())
+        };
+        unsafe {
+            sys::urkel_tx_destroy(tx);
+        }
+        result
+    }
+
+    /// Adds a committed root to the history used by the retention policy, if there is one.
+    fn record_commit(&self, root: Root) {
+        let cap = match self.retention_limit {
+            Some(count) => history_cap(count),
+            None => return,
+        };
+        let mut history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+        if history.last() != Some(&root) {
+            if history.len() == cap {
+                history.remove(0);
+            }
+            history.push(root);
+        }
+    }
+
+    /// Rewrites the store to keep only the nodes reachable from the `retain` roots and the
+    /// current root, dropping every other root.
+    ///
+    /// liburkel only ever appends to the store, so the nodes of the replaced roots pile up.
+    /// The compacted store is built next to the old one and then takes its place. If the
+    /// process is killed in the meantime, the next [`Database::open`] either completes the
+    /// switch or discards the new store, so that either of the stores opens in full.
+    ///
+    /// Returns `NotFound` if one of the roots is unknown. Takes `&mut self` since none of
+    /// the transactions, snapshots and iterators of the database may be alive while the store
+    /// is replaced. Read-only handles on the store must be [refreshed](Database::refresh).
+    /// The blobs of the dropped roots are kept until [`Database::sweep_blobs`] is called.
+    ///
+    /// The store is closed while its files are replaced. If replacing them or reopening the
+    /// store fails, it stays closed: every later use of the database panics until
+    /// [`Database::refresh`] reopens it.
+    ///
+    /// Every leaf of every retained root is rewritten, so this takes time proportional to the
+    /// size of the retained trees.
+    pub fn compact(&mut self, retain: &[Root]) -> Result<CompactReport, Error> {
+        self.check_writable()?;
+        let head = self.root();
+        let mut retained = Vec::new();
+        for root in retain {
+            if *root != head && !retained.contains(root) {
+                retained.push(*root);
+            }
+        }
+        retained.push(head);
+
+        let io_error = |source| Error::Io {
+            op: "compact",
+            source,
+        };
+        let size_before = compact::store_size(&self.prefix).map_err(io_error)?;
+        compact::rebuild(self, &self.prefix, &retained)?;
+        {
+            let _commit = self.commit_lock.lock(true, "compact")?;
+            // The old handle must not be used once its files are replaced.
+            let old = std::mem::replace(&mut self.tree, ptr::null_mut());
+            unsafe {
+                sys::urkel_close(old);
+            }
+            compact::recover(&self.prefix)?;
+            self.tree = open_tree(&self.prefix, "compact")?;
         }
-        Ok(Iter {
-            iter,
-            _marker: PhantomData,
+        let size_after = compact::store_size(&self.prefix).map_err(io_error)?;
+
+        if let Some(count) = self.retention_limit {
+            let kept = retained.len().saturating_sub(history_cap(count));
+            *self.history.lock().unwrap_or_else(PoisonError::into_inner) =
+                retained[kept..].to_vec();
+        }
+        Ok(CompactReport {
+            retained,
+            size_before,
+            size_after,
         })
     }
+
+    /// Compacts the store if the retention policy set with
+    /// [`DatabaseOptions::retention_limit`] calls for it, returning what was done.
+    ///
+    /// The direct writes apply the policy on their own. This runs a compaction deferred by a
+    /// transaction commit without waiting for the next direct write.
+    pub fn apply_retention(&mut self) -> Result<Option<CompactReport>, Error> {
+        let count = match self.retention_limit {
+            Some(count) => count,
+            None => return Ok(None),
+        };
+        let retain = {
+            let history = self.history.lock().unwrap_or_else(PoisonError::into_inner);
+            if history.len() < history_cap(count) {
+                return Ok(None);
+            }
+            history[history.len() - count..].to_vec()
+        };
+        self.compact(&retain).map(Some)
+    }
+
+    /// Returns the changes that turn the tree at `from` into the tree at `to`, ordered by key.
+    ///
+    /// Returns `NotFound` if either of the roots is unknown.
+    ///
+    /// liburkel doesn't give access to the inner nodes of the tree, so the leaves of both
+    /// trees are walked in full, even where their subtrees are identical.
+    ///
+    /// ```
+    /// # use urkel::{Change, Database, Key};
+    /// # let prefix_dir = tempfile::tempdir().unwrap();
+    /// # let db = Database::open(prefix_dir.path()).unwrap();
+    /// let mut tx = db.new_tx().unwrap();
+    /// tx.insert(&Key::new([1; 32]), b"old").unwrap();
+    /// let from = tx.commit().unwrap().root;
+    /// tx.insert(&Key::new([1; 32]), b"new").unwrap();
+    /// let to = tx.commit().unwrap().root;
+    ///
+    /// let changes = db.diff(from, to).unwrap().collect::<Result<Vec<_>, _>>().unwrap();
+    /// assert_eq!(
+    ///     changes,
+    ///     vec![Change::Changed(Key::new([1; 32]), b"old".to_vec(), b"new".to_vec())]
+    /// );
+    /// ```
+    pub fn diff(&self, from: Root, to: Root) -> Result<Diff<'_>, Error> {
+        Ok(Diff::new(self.iter(from)?, self.iter(to)?))
+    }
+
+    /// Applies the batch in a new transaction on top of the current root and commits it.
+    ///
+    /// Returns the new root. Nothing is written if any of the operations fails.
+    pub fn write(&self, batch: &WriteBatch) -> Result<Root, Error> {
+        let mut tx = self.new_tx()?;
+        tx.apply(batch)?;
+        Ok(tx.commit()?.root)
+    }
+
+    /// Returns a read-only view of the tree pinned at the given committed `root`.
+    ///
+    /// Returns `NotFound` if the root is unknown to this database. A snapshot doesn't
+    /// hold any resources of its own, so any number of them can be alive at the same time.
+    pub fn snapshot(&self, root: Root) -> Result<Snapshot<'_>, Error> {
+        // Creating a transaction at the root is the cheapest way to check that it exists.
+        let tx = unsafe { sys::urkel_tx_create(self.tree(), root.as_ptr()) };
+        if tx.is_null() {
+            return Err(Errno::fetch().into_error("snapshot", &self.prefix));
+        }
+        unsafe {
+            sys::urkel_tx_destroy(tx);
+        }
+        Ok(Snapshot { db: self, root })
+    }
+
+    /// Returns the blob store of the database.
+    pub fn blobs(&self) -> &BlobStore {
+        &self.blobs
+    }
+
+    /// Deletes the blobs that none of the leaves of the `retain` roots or of the current root
+    /// refer to, and returns how many were deleted.
+    ///
+    /// Takes `&mut self` since the blobs written by a live transaction are not referred to by
+    /// any committed root yet.
+    pub fn sweep_blobs(&mut self, retain: &[Root]) -> Result<usize, Error> {
+        self.check_writable()?;
+        let mut retain = retain.to_vec();
+        retain.push(self.root());
+        self.blobs.sweep(self, &retain)
+    }
+}
+
+/// The number of roots in the history at which a retention policy keeping `count` roots calls
+/// for a compaction. The history never holds more.
+fn history_cap(count: usize) -> usize {
+    2 * count.max(1)
+}
+
+/// Opens the store at `prefix` with liburkel, which must not happen in the middle of a write,
+/// see [`CommitLock`].
+fn open_tree(prefix: &Path, op: &'static str) -> Result<*mut sys::urkel_t, Error> {
+    let c_prefix = crate::util::path_into_c_string(prefix)?;
+    let tree = unsafe { sys::urkel_open(c_prefix.as_ptr()) };
+    if tree.is_null() {
+        return Err(Errno::fetch().into_error(op, prefix));
+    }
+    Ok(tree)
 }
 
 impl Drop for Database {
     fn drop(&mut self) {
-        unsafe {
-            sys::urkel_close(self.tree);
+        if !self.tree.is_null() {
+            unsafe {
+                sys::urkel_close(self.tree);
+            }
+        }
+    }
+}
+
+/// A read-only view of the tree at a fixed root.
+///
+/// Created by [`Database::snapshot`].
+#[derive(Clone, Copy, Debug)]
+pub struct Snapshot<'a> {
+    db: &'a Database,
+    root: Root,
+}
+
+impl<'a> Snapshot<'a> {
+    /// The root this snapshot is pinned at.
+    pub fn root(&self) -> Root {
+        self.root
+    }
+
+    pub fn get(&self, key: &Key) -> Result<Option<Vec<u8>>, Error> {
+        let mut value = Vec::with_capacity(MAX_VALUE_SIZE);
+        let mut size = 0;
+        let ret = unsafe {
+            sys::urkel_get(
+                self.db.tree(),
+                value.as_mut_ptr(),
+                &mut size as *mut usize,
+                key.as_ptr(),
+                self.root.as_ptr(),
+            )
+        };
+        if ret == 1 {
+            unsafe {
+                value.set_len(size);
+            }
+            Ok(Some(value))
+        } else {
+            let errno = Errno::fetch();
+            if errno.is_not_found() {
+                Ok(None)
+            } else {
+                Err(errno.into_error("get", &self.db.prefix))
+            }
         }
     }
+
+    pub fn has(&self, key: &Key) -> Result<bool, Error> {
+        let ret = unsafe { sys::urkel_has(self.db.tree(), key.as_ptr(), self.root.as_ptr()) };
+        if ret == 1 {
+            Ok(true)
+        } else {
+            let errno = Errno::fetch();
+            if errno.is_not_found() {
+                Ok(false)
+            } else {
+                Err(errno.into_error("has", &self.db.prefix))
+            }
+        }
+    }
+
+    pub fn prove(&self, key: &Key) -> Result<Proof, Error> {
+        self.db.prove(key, self.root)
+    }
+
+    pub fn prove_many(&self, keys: &[Key]) -> Result<MultiProof, Error> {
+        self.db.prove_many(keys, self.root)
+    }
+
+    pub fn iter(&self) -> Result<Iter<'a>, Error> {
+        self.db.iter(self.root)
+    }
+}
+
+/// The kind of a change made to a key, relative to the root the transaction started from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Inserted,
+    Updated,
+    Removed,
+}
+
+/// Describes what a call to [`Transaction::commit`] has written.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitReport {
+    /// The root after the commit.
+    pub root: Root,
+    /// The root the transaction was created at, or the root of the previous commit.
+    pub prev_root: Root,
+    /// The keys that didn't exist at `prev_root`, in ascending order.
+    pub inserted: Vec<Key>,
+    /// The keys whose value differs from the one at `prev_root`, in ascending order.
+    pub updated: Vec<Key>,
+    /// The keys that existed at `prev_root` and were removed, in ascending order.
+    pub removed: Vec<Key>,
+}
+
+/// Identifies a savepoint within a [`Transaction`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SavepointId(u64);
+
+struct Savepoint {
+    id: SavepointId,
+    /// The length of the undo log at the time the savepoint was taken.
+    undo_len: usize,
+    /// The target of the last revert at the time the savepoint was taken.
+    reverted_to: Option<Root>,
 }
 
 pub struct Transaction<'a> {
+    db: &'a Database,
     tx: *mut sys::urkel_tx_t,
-    _marker: PhantomData<&'a ()>,
+    /// The root the transaction was created at or last committed.
+    base: Root,
+    /// The values at `base` of all the keys touched since then.
+    originals: BTreeMap<Key, Option<Vec<u8>>>,
+    /// The root the transaction was last reverted to since `base`. The keys that differ
+    /// between the two are not in `originals`, they are only diffed when the changes are
+    /// needed.
+    reverted_to: Option<Root>,
+    /// The stack of the active savepoints, innermost last.
+    savepoints: Vec<Savepoint>,
+    /// The previous values of the keys written while there are active savepoints.
+    undo: Vec<(Key, Option<Vec<u8>>)>,
+    next_savepoint: u64,
 }
 
+// A transaction is only ever touched through its owner and is only tied to the `Database`,
+// which is `Sync`, so it can be moved to another thread.
+//
+// It is not `Sync` though: even the reads may resolve nodes into the in-memory tree of the
+// transaction, so the transaction cannot be accessed from several threads at once. For the same
+// reason an iterator borrows the transaction mutably, so that no read runs while it walks the
+// tree.
 unsafe impl Send for Transaction<'_> {}
-unsafe impl Sync for Transaction<'_> {}
 
 impl<'a> Transaction<'a> {
+    fn new(db: &'a Database, tx: *mut sys::urkel_tx_t) -> Self {
+        let mut tx = Transaction {
+            db,
+            tx,
+            base: Root::EMPTY,
+            originals: BTreeMap::new(),
+            reverted_to: None,
+            savepoints: Vec::new(),
+            undo: Vec::new(),
+            next_savepoint: 0,
+        };
+        tx.base = tx.root();
+        tx
+    }
+
     /// Empty tx root is all zeroes.
-    pub fn root(&self) -> [u8; 32] {
-        let mut root = [0; 32];
+    pub fn root(&self) -> Root {
+        let mut root = Root::EMPTY;
         unsafe { sys::urkel_tx_root(self.tx, root.as_mut_ptr()) }
         root
     }
 
     /// Doesn't support values more than 1024 bytes long.
-    pub fn insert(&self, key: &[u8], value: &[u8]) -> Result<(), Error> {
+    pub fn insert(&mut self, key: &Key, value: &[u8]) -> Result<(), Error> {
         if value.len() > MAX_VALUE_SIZE {
             return Err(Error::ValueTooLarge);
         }
+        let prior = self.before_write(key)?;
+        self.raw_insert(key, value)?;
+        self.after_write(key, prior);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &Key) -> Result<(), Error> {
+        let prior = self.before_write(key)?;
+        self.raw_remove(key)?;
+        self.after_write(key, prior);
+        Ok(())
+    }
+
+    fn raw_insert(&mut self, key: &Key, value: &[u8]) -> Result<(), Error> {
         let ret =
             unsafe { sys::urkel_tx_insert(self.tx, key.as_ptr(), value.as_ptr(), value.len()) };
         if ret == 0 {
-            return Err(Errno::fetch().into_error());
+            return Err(Errno::fetch().into_error("insert", &self.db.prefix));
         }
         Ok(())
     }
 
-    pub fn remove(&self, key: &[u8]) -> Result<(), Error> {
+    fn raw_remove(&mut self, key: &Key) -> Result<(), Error> {
         let ret = unsafe { sys::urkel_tx_remove(self.tx, key.as_ptr()) };
         if ret == 0 {
-            return Err(Errno::fetch().into_error());
+            return Err(Errno::fetch().into_error("remove", &self.db.prefix));
         }
         Ok(())
     }
 
-    pub fn has(&self, key: &[u8]) -> Result<bool, Error> {
+    fn raw_inject(&mut self, root: Root, op: &'static str) -> Result<(), Error> {
+        let ret = unsafe { sys::urkel_tx_inject(self.tx, root.as_ptr()) };
+        if ret == 0 {
+            return Err(Errno::fetch().into_error(op, &self.db.prefix));
+        }
+        Ok(())
+    }
+
+    pub fn has(&self, key: &Key) -> Result<bool, Error> {
         let ret = unsafe { sys::urkel_tx_has(self.tx, key.as_ptr()) };
         if ret == 1 {
             Ok(true)
@@ -165,12 +741,12 @@ impl<'a> Transaction<'a> {
             if errno.is_not_found() {
                 Ok(false)
             } else {
-                return Err(errno.into_error());
+                Err(errno.into_error("has", &self.db.prefix))
             }
         }
     }
 
-    pub fn prove(&self, key: &[u8]) -> Result<Proof, Error> {
+    pub fn prove(&self, key: &Key) -> Result<Proof, Error> {
         let mut proof_raw = ptr::null_mut();
         let mut proof_len = 0usize;
         let ret = unsafe {
@@ -184,7 +760,7 @@ impl<'a> Transaction<'a> {
         if ret == 0 {
             // we assume that the buf wasn't allocated.
             debug_assert_eq!(proof_raw, ptr::null_mut());
-            return Err(Errno::fetch().into_error());
+            return Err(Errno::fetch().into_error("prove", &self.db.prefix));
         }
 
         let proof = unsafe {
@@ -195,23 +771,253 @@ impl<'a> Transaction<'a> {
         Ok(proof)
     }
 
-    pub fn revert(&self, root: [u8; 32]) -> Result<(), Error> {
-        let ret = unsafe { sys::urkel_tx_inject(self.tx, root.as_ptr()) };
-        if ret == 0 {
-            return Err(Errno::fetch().into_error());
+    /// Proves the presence or absence of each of `keys` against the current root of the
+    /// transaction.
+    pub fn prove_many(&self, keys: &[Key]) -> Result<MultiProof, Error> {
+        let proofs = keys
+            .iter()
+            .map(|key| self.prove(key))
+            .collect::<Result<_, _>>()?;
+        Ok(MultiProof::from_proofs(proofs))
+    }
+
+    /// Puts the transaction at the committed `root`, discarding its changes.
+    ///
+    /// The revert itself only walks the trees while there are active savepoints, to log the
+    /// values it replaces. The next [`pending_changes`](Self::pending_changes) or
+    /// [`commit`](Self::commit) then diffs the base of the transaction against `root`, which
+    /// walks both trees.
+    pub fn revert(&mut self, root: Root) -> Result<(), Error> {
+        let undo = if self.savepoints.is_empty() {
+            Vec::new()
+        } else {
+            let target = self.db.iter(root)?;
+            DiffLeaves::new(self.iter()?, target)
+                .map(|leaf| leaf.map(|(key, current, _)| (key, current)))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        self.raw_inject(root, "revert")?;
+        self.undo.extend(undo);
+        self.reverted_to = Some(root);
+        Ok(())
+    }
+
+    /// Applies all the operations of the batch in order, or none of them.
+    ///
+    /// The values are checked before anything is written. If an operation fails nonetheless,
+    /// for example because it removes a key that doesn't exist, the operations applied before
+    /// it are undone and the transaction is left as it was.
+    pub fn apply(&mut self, batch: &WriteBatch) -> Result<(), Error> {
+        batch.validate()?;
+        let savepoint = self.savepoint();
+        let result = batch.ops().iter().try_for_each(|op| match op {
+            BatchOp::Put(key, value) => self.insert(key, value),
+            BatchOp::Delete(key) => self.remove(key),
+        });
+        if result.is_err() {
+            self.rollback_to(savepoint)?;
+        }
+        self.release(savepoint)?;
+        result
+    }
+
+    /// Marks the current state of the transaction, so that the changes made after this point
+    /// can be undone with [`rollback_to`](Self::rollback_to).
+    ///
+    /// Savepoints nest: rolling back to or releasing a savepoint also releases all the
+    /// savepoints taken after it. Committing or clearing the transaction releases all of them.
+    ///
+    /// ```
+    /// # use urkel::{Database, Key};
+    /// # let prefix_dir = tempfile::tempdir().unwrap();
+    /// # let db = Database::open(prefix_dir.path()).unwrap();
+    /// let mut tx = db.new_tx().unwrap();
+    /// tx.insert(&Key::new([1; 32]), b"kept").unwrap();
+    ///
+    /// let savepoint = tx.savepoint();
+    /// tx.insert(&Key::new([2; 32]), b"undone").unwrap();
+    /// tx.rollback_to(savepoint).unwrap();
+    ///
+    /// assert!(tx.has(&Key::new([1; 32])).unwrap());
+    /// assert!(!tx.has(&Key::new([2; 32])).unwrap());
+    /// ```
+    pub fn savepoint(&mut self) -> SavepointId {
+        let id = SavepointId(self.next_savepoint);
+        self.next_savepoint += 1;
+        self.savepoints.push(Savepoint {
+            id,
+            undo_len: self.undo.len(),
+            reverted_to: self.reverted_to,
+        });
+        id
+    }
+
+    /// Undoes all the changes made since the given savepoint was taken.
+    ///
+    /// The savepoint stays active and can be rolled back to again, while the savepoints taken
+    /// after it are released.
+    pub fn rollback_to(&mut self, savepoint: SavepointId) -> Result<(), Error> {
+        let index = self.savepoint_index(savepoint)?;
+        let Savepoint {
+            undo_len,
+            reverted_to,
+            ..
+        } = self.savepoints[index];
+        while self.undo.len() > undo_len {
+            let (key, prior) = self.undo.pop().expect("the log is longer than `undo_len`");
+            match prior {
+                Some(value) => self.raw_insert(&key, &value)?,
+                None => self.raw_remove(&key)?,
+            }
         }
+        self.reverted_to = reverted_to;
+        self.savepoints.truncate(index + 1);
         Ok(())
     }
 
-    pub fn commit(&self) -> Result<(), Error> {
-        let ret = unsafe { sys::urkel_tx_commit(self.tx) };
-        if ret == 0 {
-            return Err(Errno::fetch().into_error());
+    /// Releases the given savepoint and all the savepoints taken after it, keeping the changes.
+    pub fn release(&mut self, savepoint: SavepointId) -> Result<(), Error> {
+        let index = self.savepoint_index(savepoint)?;
+        self.savepoints.truncate(index);
+        if self.savepoints.is_empty() {
+            self.undo.clear();
+        }
+        Ok(())
+    }
+
+    /// Discards all the changes made since the transaction was created or last committed and
+    /// releases all the savepoints.
+    pub fn clear(&mut self) -> Result<(), Error> {
+        // `urkel_tx_clear` throws away the in-memory tree of the transaction. Put the
+        // transaction back at its base afterwards.
+        unsafe {
+            sys::urkel_tx_clear(self.tx);
         }
+        self.raw_inject(self.base, "clear")?;
+        self.originals.clear();
+        self.reverted_to = None;
+        self.savepoints.clear();
+        self.undo.clear();
         Ok(())
     }
 
-    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+    fn savepoint_index(&self, savepoint: SavepointId) -> Result<usize, Error> {
+        self.savepoints
+            .iter()
+            .position(|sp| sp.id == savepoint)
+            .ok_or(Error::UnknownSavepoint)
+    }
+
+    /// Writes the changes to the database and makes the new root the head of the tree.
+    ///
+    /// Returns a report of what has changed since the transaction was created or last
+    /// committed.
+    pub fn commit(&mut self) -> Result<CommitReport, Error> {
+        self.db.check_writable()?;
+        let changes = self.pending_changes()?;
+        let ret = {
+            let _commit = self.db.commit_lock.lock(true, "commit")?;
+            unsafe { sys::urkel_tx_commit(self.tx) }
+        };
+        if ret == 0 {
+            return Err(Errno::fetch().into_error("commit", &self.db.prefix));
+        }
+
+        let mut report = CommitReport {
+            root: self.root(),
+            prev_root: self.base,
+            inserted: Vec::new(),
+            updated: Vec::new(),
+            removed: Vec::new(),
+        };
+        for (key, kind) in changes {
+            match kind {
+                ChangeKind::Inserted => report.inserted.push(key),
+                ChangeKind::Updated => report.updated.push(key),
+                ChangeKind::Removed => report.removed.push(key),
+            }
+        }
+        self.db.record_commit(report.root);
+        self.base = report.root;
+        self.originals.clear();
+        self.reverted_to = None;
+        self.savepoints.clear();
+        self.undo.clear();
+        Ok(report)
+    }
+
+    /// Returns `true` if the state of the transaction differs from the root it was created at
+    /// or last committed.
+    pub fn is_dirty(&self) -> bool {
+        self.root() != self.base
+    }
+
+    /// Returns the keys changed since the transaction was created or last committed, in
+    /// ascending order.
+    ///
+    /// A key that was changed and then set back to its original value is not reported.
+    pub fn pending_changes(&self) -> Result<Vec<(Key, ChangeKind)>, Error> {
+        let mut reverted = BTreeMap::new();
+        if let Some(root) = self.reverted_to {
+            for leaf in DiffLeaves::new(self.db.iter(self.base)?, self.db.iter(root)?) {
+                let (key, original, _) = leaf?;
+                reverted.insert(key, original);
+            }
+        }
+        let mut originals = self.originals.iter().collect::<BTreeMap<_, _>>();
+        originals.extend(reverted.iter());
+
+        let mut changes = Vec::new();
+        for (key, original) in originals {
+            let kind = match (original, self.get(key)?) {
+                (None, Some(_)) => ChangeKind::Inserted,
+                (Some(_), None) => ChangeKind::Removed,
+                (Some(original), Some(ref current)) if original != current => ChangeKind::Updated,
+                _ => continue,
+            };
+            changes.push((*key, kind));
+        }
+        Ok(changes)
+    }
+
+    /// Reads the current value of `key` if it's needed to track the upcoming write: either the
+    /// key is changed for the first time since the base, or there are savepoints to roll back
+    /// to.
+    ///
+    /// Returns the value to put into the undo log, if any.
+    fn before_write(&mut self, key: &Key) -> Result<Option<Option<Vec<u8>>>, Error> {
+        let first_write = !self.originals.contains_key(key);
+        if !first_write && self.savepoints.is_empty() {
+            return Ok(None);
+        }
+        let current = self.get(key)?;
+        if first_write {
+            // After a revert, the current value is not necessarily the one at the base.
+            let original = match self.reverted_to {
+                Some(_) if self.base == Root::EMPTY => None,
+                Some(_) => Snapshot {
+                    db: self.db,
+                    root: self.base,
+                }
+                .get(key)?,
+                None => current.clone(),
+            };
+            self.originals.insert(*key, original);
+        }
+        Ok(if self.savepoints.is_empty() {
+            None
+        } else {
+            Some(current)
+        })
+    }
+
+    fn after_write(&mut self, key: &Key, prior: Option<Option<Vec<u8>>>) {
+        if let Some(prior) = prior {
+            self.undo.push((*key, prior));
+        }
+    }
+
+    pub fn get(&self, key: &Key) -> Result<Option<Vec<u8>>, Error> {
         let mut value = Vec::with_capacity(MAX_VALUE_SIZE);
         let mut size = 0;
         let ret = unsafe {
@@ -232,72 +1038,55 @@ impl<'a> Transaction<'a> {
             if errno.is_not_found() {
                 Ok(None)
             } else {
-                return Err(errno.into_error());
+                Err(errno.into_error("get", &self.db.prefix))
             }
         }
     }
 
-    pub fn iter(&self) -> Result<Iter, Error> {
-        let iter = unsafe { sys::urkel_iter_create(self.tx) };
-        if iter.is_null() {
-            return Err(Errno::fetch().into_error());
-        }
-        Ok(Iter {
-            iter,
-            _marker: PhantomData,
-        })
-    }
-}
-
-impl<'a> Drop for Transaction<'a> {
-    fn drop(&mut self) {
-        unsafe {
-            sys::urkel_tx_destroy(self.tx);
-        }
+    /// Writes `blob` to the blob store and inserts its hash under `key`.
+    ///
+    /// Unlike the tree, the blob store is written to right away. If the transaction isn't
+    /// committed, the blob is deleted by the next [`Database::sweep_blobs`].
+    pub fn put_blob(&mut self, key: &Key, blob: &[u8]) -> Result<(), Error> {
+        self.db.check_writable()?;
+        let hash = self.db.blobs.put(blob)?;
+        self.insert(key, &hash)
     }
-}
 
-pub struct Iter<'a> {
-    iter: *mut sys::urkel_iter_t,
-    _marker: PhantomData<&'a mut ()>,
-}
-
-unsafe impl Send for Iter<'_> {}
-unsafe impl Sync for Iter<'_> {}
-
-impl<'a> Iter<'a> {
-    pub fn next(&self) -> Result<Option<(Key, Vec<u8>)>, Error> {
-        let mut k = [0; 32];
-        let mut v = Vec::with_capacity(MAX_VALUE_SIZE);
-        let mut size = 0;
-        let ret = unsafe {
-            sys::urkel_iter_next(
-                self.iter,
-                k.as_mut_ptr(),
-                v.as_mut_ptr(),
-                &mut size as *mut usize,
-            )
+    /// Reads the blob whose hash is stored under `key`, checking it against the hash.
+    ///
+    /// Fails with `InvalidBlobReference` if the value of `key` is not a hash, and with
+    /// `MissingBlob` if the blob store doesn't have it.
+    pub fn get_blob(&self, key: &Key) -> Result<Option<Vec<u8>>, Error> {
+        let hash = match self.get(key)? {
+            Some(hash) => hash,
+            None => return Ok(None),
         };
-        if ret == 1 {
-            unsafe {
-                v.set_len(size);
-            }
-            return Ok(Some((k, v)));
+        let hash =
+            <[u8; 32]>::try_from(hash.as_slice()).map_err(|_| Error::InvalidBlobReference(*key))?;
+        match self.db.blobs.get(&hash)? {
+            Some(blob) => Ok(Some(blob)),
+            None => Err(Error::MissingBlob(*key)),
         }
+    }
 
-        let errno = Errno::fetch();
-        if errno.is_iter_end() {
-            return Ok(None);
-        } else {
-            return Err(errno.into_error());
+    /// Iterates over the leaves of the tree as seen by this transaction.
+    ///
+    /// The iterator borrows the transaction mutably, so the transaction can't be used until
+    /// the iterator is dropped.
+    pub fn iter(&mut self) -> Result<Iter<'_>, Error> {
+        let iter = unsafe { sys::urkel_iter_create(self.tx) };
+        if iter.is_null() {
+            return Err(Errno::fetch().into_error("iter", &self.db.prefix));
         }
+        Ok(Iter::new(iter, &self.db.prefix))
     }
 }
 
-impl<'a> Drop for Iter<'a> {
+impl<'a> Drop for Transaction<'a> {
     fn drop(&mut self) {
         unsafe {
-            sys::urkel_iter_destroy(self.iter);
+            sys::urkel_tx_destroy(self.tx);
         }
     }
 }
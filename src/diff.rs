@@ -0,0 +1,126 @@
+use crate::error::Error;
+use crate::iter::Iter;
+use crate::Key;
+use std::cmp::Ordering;
+
+/// Walks the leaves of two trees side by side and yields the keys that differ between them,
+/// together with the value in each of the trees.
+pub(crate) struct DiffLeaves<'a> {
+    from: Iter<'a>,
+    to: Iter<'a>,
+    from_next: Option<(Key, Vec<u8>)>,
+    to_next: Option<(Key, Vec<u8>)>,
+    done: bool,
+}
+
+impl<'a> DiffLeaves<'a> {
+    pub(crate) fn new(from: Iter<'a>, to: Iter<'a>) -> Self {
+        DiffLeaves {
+            from,
+            to,
+            from_next: None,
+            to_next: None,
+            done: false,
+        }
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn next_diff(&mut self) -> Result<Option<(Key, Option<Vec<u8>>, Option<Vec<u8>>)>, Error> {
+        loop {
+            // Both iterators are fused, so refilling an exhausted side is cheap.
+            if self.from_next.is_none() {
+                self.from_next = self.from.next().transpose()?;
+            }
+            if self.to_next.is_none() {
+                self.to_next = self.to.next().transpose()?;
+            }
+            let ordering = match (&self.from_next, &self.to_next) {
+                (None, None) => return Ok(None),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((from_key, _)), Some((to_key, _))) => from_key.cmp(to_key),
+            };
+            match ordering {
+                Ordering::Less => {
+                    let (key, old) = self.from_next.take().expect("checked above");
+                    return Ok(Some((key, Some(old), None)));
+                }
+                Ordering::Greater => {
+                    let (key, new) = self.to_next.take().expect("checked above");
+                    return Ok(Some((key, None, Some(new))));
+                }
+                Ordering::Equal => {
+                    let (key, old) = self.from_next.take().expect("checked above");
+                    let (_, new) = self.to_next.take().expect("checked above");
+                    if old != new {
+                        return Ok(Some((key, Some(old), Some(new))));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for DiffLeaves<'a> {
+    type Item = Result<(Key, Option<Vec<u8>>, Option<Vec<u8>>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_diff().transpose();
+        if !matches!(next, Some(Ok(_))) {
+            self.done = true;
+        }
+        next
+    }
+}
+
+/// A difference in a single key between two roots.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change {
+    /// The key exists only at the target root.
+    Added(Key, Vec<u8>),
+    /// The key exists only at the source root. Holds the old value.
+    Removed(Key, Vec<u8>),
+    /// The key exists at both roots with different values. Holds the old and the new value.
+    Changed(Key, Vec<u8>, Vec<u8>),
+}
+
+impl Change {
+    pub fn key(&self) -> &Key {
+        match self {
+            Change::Added(key, _) | Change::Removed(key, _) | Change::Changed(key, _, _) => key,
+        }
+    }
+}
+
+/// An iterator over the differences between two roots in ascending key order.
+///
+/// Created by [`Database::diff`](crate::Database::diff).
+pub struct Diff<'a> {
+    leaves: DiffLeaves<'a>,
+}
+
+impl<'a> Diff<'a> {
+    pub(crate) fn new(from: Iter<'a>, to: Iter<'a>) -> Self {
+        Diff {
+            leaves: DiffLeaves::new(from, to),
+        }
+    }
+}
+
+impl<'a> Iterator for Diff<'a> {
+    type Item = Result<Change, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.leaves.next().map(|leaf| {
+            leaf.map(|(key, old, new)| match (old, new) {
+                (None, Some(new)) => Change::Added(key, new),
+                (Some(old), None) => Change::Removed(key, old),
+                (Some(old), Some(new)) => Change::Changed(key, old, new),
+                (None, None) => unreachable!("a key is present in at least one of the trees"),
+            })
+        })
+    }
+}
@@ -1,19 +1,134 @@
+#[cfg(feature = "native")]
+use crate::{Key, Root};
+use core::fmt;
+#[cfg(feature = "native")]
+use std::path::{Path, PathBuf};
+
+/// An error returned by the database.
+///
+/// With the `serde` feature the error can be serialized, e.g. to report it from an API, but not
+/// deserialized: `op` names the failed operation with a static string. I/O errors are
+/// serialized as their message.
+#[cfg(feature = "native")]
 #[derive(thiserror::Error, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Error {
     #[error("a path is incorrect")]
     PathErr,
+    #[error("a key must be 32 bytes long, got {0}")]
+    InvalidKeyLength(usize),
     #[error("the value is larger than supported")]
     ValueTooLarge,
     #[error("given value is not found")]
     NotFound,
-    #[error("unknown error happened")]
-    Unknown,
+    #[error("the savepoint doesn't exist or was released")]
+    UnknownSavepoint,
+    #[error("the store at {} is corrupted ({op})", path.display())]
+    Corruption { op: &'static str, path: PathBuf },
+    #[error("the tree was not updated ({op})")]
+    NoUpdate { op: &'static str },
+    #[error("failed to write to the store at {} ({op})", path.display())]
+    BadWrite { op: &'static str, path: PathBuf },
+    #[error("failed to open the store at {} ({op})", path.display())]
+    BadOpen { op: &'static str, path: PathBuf },
+    #[error("invalid argument ({op})")]
+    InvalidArgument { op: &'static str },
+    #[error("unknown error {errno} ({op})")]
+    Unknown { op: &'static str, errno: u32 },
+    #[error("no store exists at {}", path.display())]
+    DoesNotExist { path: PathBuf },
+    #[error("a store already exists at {}", path.display())]
+    AlreadyExists { path: PathBuf },
+    #[error("the database was opened read-only")]
+    ReadOnly,
+    #[error("the store is already opened for writing (holder pid: {holder_pid:?})")]
+    Locked { holder_pid: Option<u32> },
+    #[error("expected the root {expected}, found {actual}")]
+    RootMismatch { expected: Root, actual: Root },
+    #[error("the value of {0} is not a valid large value")]
+    InvalidLargeValue(Key),
+    #[error("the value of {0} is not a blob hash")]
+    InvalidBlobReference(Key),
+    #[error("the blob of {0} is missing from the blob store")]
+    MissingBlob(Key),
+    #[error("the blob at {} doesn't match its hash", path.display())]
+    CorruptBlob { path: PathBuf },
+    #[error("I/O error ({op})")]
+    Io {
+        op: &'static str,
+        #[cfg_attr(
+            feature = "serde",
+            serde(serialize_with = "crate::serde_impl::serialize_display")
+        )]
+        source: std::io::Error,
+    },
+    #[error("the snapshot is malformed: {0}")]
+    MalformedSnapshot(&'static str),
 }
 
-impl Error {}
+#[cfg(feature = "native")]
+impl Error {
+    /// Returns the raw liburkel error code behind this error, if there is one.
+    pub fn errno(&self) -> Option<u32> {
+        match self {
+            Error::PathErr
+            | Error::InvalidKeyLength(_)
+            | Error::ValueTooLarge
+            | Error::UnknownSavepoint
+            | Error::DoesNotExist { .. }
+            | Error::AlreadyExists { .. }
+            | Error::RootMismatch { .. }
+            | Error::ReadOnly
+            | Error::Locked { .. }
+            | Error::InvalidLargeValue(_)
+            | Error::InvalidBlobReference(_)
+            | Error::MissingBlob(_)
+            | Error::CorruptBlob { .. }
+            | Error::Io { .. }
+            | Error::MalformedSnapshot(_) => None,
+            Error::NotFound => Some(urkel_sys::URKEL_ENOTFOUND),
+            Error::Corruption { .. } => Some(urkel_sys::URKEL_ECORRUPTION),
+            Error::NoUpdate { .. } => Some(urkel_sys::URKEL_ENOUPDATE),
+            Error::BadWrite { .. } => Some(urkel_sys::URKEL_EBADWRITE),
+            Error::BadOpen { .. } => Some(urkel_sys::URKEL_EBADOPEN),
+            Error::InvalidArgument { .. } => Some(urkel_sys::URKEL_EINVAL),
+            Error::Unknown { errno, .. } => Some(*errno),
+        }
+    }
+}
 
-pub(crate) struct Errno(u32);
+/// An error parsing a hex-encoded 32-byte value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ParseHexError {
+    InvalidLength(usize),
+    InvalidCharacter(usize),
+    OddLength(usize),
+}
 
+impl fmt::Display for ParseHexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseHexError::InvalidLength(len) => {
+                write!(f, "expected 64 hex characters, got {}", len)
+            }
+            ParseHexError::InvalidCharacter(pos) => {
+                write!(f, "invalid hex character at position {}", pos)
+            }
+            ParseHexError::OddLength(len) => {
+                write!(f, "expected an even number of hex characters, got {}", len)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseHexError {}
+
+#[cfg(feature = "native")]
+pub(crate) struct Errno(pub(crate) u32);
+
+#[cfg(feature = "native")]
 impl Errno {
     pub fn fetch() -> Self {
         let errno = unsafe { *urkel_sys::__urkel_get_errno() };
@@ -28,14 +143,26 @@ impl Errno {
         self.0 == urkel_sys::URKEL_EITEREND
     }
 
-    pub fn into_error(self) -> Error {
+    /// Converts the error code into an `Error`, given the operation that failed and the prefix
+    /// of the store it operated on.
+    pub fn into_error(self, op: &'static str, path: &Path) -> Error {
         match self.0 {
             urkel_sys::URKEL_ENOTFOUND => Error::NotFound,
-            err => {
-                dbg!(err);
-                // TODO:
-                Error::Unknown
-            }
+            urkel_sys::URKEL_ECORRUPTION => Error::Corruption {
+                op,
+                path: path.to_path_buf(),
+            },
+            urkel_sys::URKEL_ENOUPDATE => Error::NoUpdate { op },
+            urkel_sys::URKEL_EBADWRITE => Error::BadWrite {
+                op,
+                path: path.to_path_buf(),
+            },
+            urkel_sys::URKEL_EBADOPEN => Error::BadOpen {
+                op,
+                path: path.to_path_buf(),
+            },
+            urkel_sys::URKEL_EINVAL => Error::InvalidArgument { op },
+            errno => Error::Unknown { op, errno },
         }
     }
 
@@ -0,0 +1,360 @@
+use crate::util::blake2b_256;
+use crate::{Change, Database, DatabaseOptions, Error, Key, Root, MAX_VALUE_SIZE};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+const MAGIC: [u8; 8] = *b"URKLSNAP";
+const DELTA_MAGIC: [u8; 8] = *b"URKLDLTA";
+const VERSION: u8 = 0;
+
+const TAG_END: u8 = 0;
+const TAG_LEAF: u8 = 1;
+const TAG_REMOVED: u8 = 2;
+
+/// The number of leaves imported per commit, which bounds the memory used by the import.
+const IMPORT_BATCH: u64 = 4096;
+
+/// A checksum built as a hash chain over the records of a snapshot, so that it can be computed
+/// while streaming: the state is the hash of the previous state followed by the record.
+struct Checksum {
+    state: [u8; 32],
+    buf: Vec<u8>,
+}
+
+impl Checksum {
+    fn new() -> Self {
+        Checksum {
+            state: [0; 32],
+            buf: Vec::new(),
+        }
+    }
+
+    fn update(&mut self, record: &[u8]) {
+        self.buf.clear();
+        self.buf.extend_from_slice(&self.state);
+        self.buf.extend_from_slice(record);
+        self.state = blake2b_256(&self.buf);
+    }
+}
+
+impl Database {
+    /// Writes every leaf of the tree at `root` to `writer` as a snapshot, which
+    /// [`Database::import`] turns back into a database. Returns the number of leaves written.
+    ///
+    /// The leaves are streamed one by one, so memory use doesn't depend on the size of the tree.
+    /// The format is, with all integers little-endian:
+    ///
+    /// ```text
+    /// header:  magic:"URKLSNAP" version:u8 root:[u8; 32]
+    /// leaves:  tag:u8 = 1, key:[u8; 32], size:u16, value:[u8; size]    in ascending key order
+    /// trailer: tag:u8 = 0, count:u64, checksum:[u8; 32]
+    /// ```
+    ///
+    /// where `checksum` is the last state of a BLAKE2b-256 hash chain, starting from zeros, over
+    /// the header, each leaf and the trailer up to the count.
+    pub fn export(&self, root: Root, writer: impl Write) -> Result<u64, Error> {
+        let mut writer = SnapshotWriter::new(writer, "export");
+        let header = writer.record();
+        header.extend_from_slice(&MAGIC);
+        header.push(VERSION);
+        header.extend_from_slice(root.as_bytes());
+        writer.emit()?;
+        for leaf in self.iter(root)? {
+            let (key, value) = leaf?;
+            writer.put(&key, &value)?;
+        }
+        writer.finish()
+    }
+
+    /// Creates a new store at `prefix` from a snapshot written by [`Database::export`].
+    ///
+    /// Fails with `AlreadyExists` if there is a store at `prefix` already, with
+    /// `MalformedSnapshot` if the snapshot is truncated, corrupted or of an unknown version, and
+    /// with `RootMismatch` if the leaves don't add up to the root in the header. The new store
+    /// is destroyed on failure.
+    ///
+    /// The leaves are committed in batches to keep memory use bounded, which leaves some
+    /// intermediate roots in the store. [`Database::compact`] drops them.
+    pub fn import(prefix: impl AsRef<Path>, reader: impl Read) -> Result<Database, Error> {
+        let prefix = prefix.as_ref();
+        let db = DatabaseOptions::new().error_if_exists(true).open(prefix)?;
+        match import_into(&db, reader) {
+            Ok(()) => Ok(db),
+            Err(err) => {
+                drop(db);
+                let _ = Database::destroy(prefix);
+                Err(err)
+            }
+        }
+    }
+
+    /// Writes the changes that turn the tree at `from` into the tree at `to` to `writer`, to be
+    /// applied with [`Database::apply_delta`]. Returns the number of changed keys.
+    ///
+    /// The format is the one of [`Database::export`] with another magic, both roots in the
+    /// header and a record for each removed key:
+    ///
+    /// ```text
+    /// header:  magic:"URKLDLTA" version:u8 from:[u8; 32] to:[u8; 32]
+    /// changes: tag:u8 = 1, key:[u8; 32], size:u16, value:[u8; size]    inserted or updated
+    ///          tag:u8 = 2, key:[u8; 32]                                 removed
+    /// trailer: tag:u8 = 0, count:u64, checksum:[u8; 32]
+    /// ```
+    ///
+    /// Both trees are walked in full, see [`Database::diff`], but only the changes are written.
+    pub fn export_delta(&self, from: Root, to: Root, writer: impl Write) -> Result<u64, Error> {
+        let mut writer = SnapshotWriter::new(writer, "export_delta");
+        let header = writer.record();
+        header.extend_from_slice(&DELTA_MAGIC);
+        header.push(VERSION);
+        header.extend_from_slice(from.as_bytes());
+        header.extend_from_slice(to.as_bytes());
+        writer.emit()?;
+        for change in self.diff(from, to)? {
+            match change? {
+                Change::Added(key, value) | Change::Changed(key, _, value) => {
+                    writer.put(&key, &value)?
+                }
+                Change::Removed(key, _) => writer.remove(&key)?,
+            }
+        }
+        writer.finish()
+    }
+
+    /// Applies a delta written by [`Database::export_delta`] on top of its source root, commits
+    /// the result and returns it. The result becomes the head of the tree, whatever the head
+    /// was before.
+    ///
+    /// Fails with `NotFound` if the database doesn't have the source root, with
+    /// `MalformedSnapshot` if the delta is truncated or corrupted, and with `RootMismatch` if the
+    /// result isn't the target root of the delta. Nothing is committed unless the whole delta
+    /// checks out, so the changes are held in memory until then.
+    pub fn apply_delta(&self, reader: impl Read) -> Result<Root, Error> {
+        self.check_writable()?;
+        let mut reader = SnapshotReader::new(reader, &DELTA_MAGIC, "apply_delta")?;
+        let from = Root::new(reader.read()?);
+        let to = Root::new(reader.read()?);
+        reader.finish_record();
+
+        let mut tx = self.new_tx_at(from)?;
+        while let Some(record) = reader.next_record()? {
+            match record {
+                Record::Leaf(key, value) => tx.insert(&key, &value)?,
+                Record::Removed(key) => tx.remove(&key).map_err(|err| match err {
+                    Error::NotFound => Error::MalformedSnapshot("a removed key doesn't exist"),
+                    err => err,
+                })?,
+            }
+        }
+
+        if tx.root() != to {
+            return Err(Error::RootMismatch {
+                expected: to,
+                actual: tx.root(),
+            });
+        }
+        tx.commit()?;
+        Ok(to)
+    }
+}
+
+fn import_into(db: &Database, reader: impl Read) -> Result<(), Error> {
+    let mut reader = SnapshotReader::new(reader, &MAGIC, "import")?;
+    let root = Root::new(reader.read()?);
+    reader.finish_record();
+
+    let mut tx = db.new_tx()?;
+    let mut count = 0u64;
+    while let Some(record) = reader.next_record()? {
+        match record {
+            Record::Leaf(key, value) => tx.insert(&key, &value)?,
+            Record::Removed(_) => return Err(Error::MalformedSnapshot("unknown record")),
+        }
+        count += 1;
+        if count.is_multiple_of(IMPORT_BATCH) {
+            tx.commit()?;
+        }
+    }
+
+    if tx.root() != root {
+        return Err(Error::RootMismatch {
+            expected: root,
+            actual: tx.root(),
+        });
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Writes the records of a snapshot or a delta, feeding them to the checksum.
+struct SnapshotWriter<W: Write> {
+    inner: BufWriter<W>,
+    op: &'static str,
+    checksum: Checksum,
+    /// The bytes of the current record.
+    record: Vec<u8>,
+    count: u64,
+}
+
+impl<W: Write> SnapshotWriter<W> {
+    fn new(writer: W, op: &'static str) -> Self {
+        SnapshotWriter {
+            inner: BufWriter::new(writer),
+            op,
+            checksum: Checksum::new(),
+            record: Vec::with_capacity(1 + 32 + 2 + MAX_VALUE_SIZE),
+            count: 0,
+        }
+    }
+
+    /// Returns the buffer of the next record, to be written out with `emit`.
+    fn record(&mut self) -> &mut Vec<u8> {
+        self.record.clear();
+        &mut self.record
+    }
+
+    fn emit(&mut self) -> Result<(), Error> {
+        self.checksum.update(&self.record);
+        let op = self.op;
+        self.inner
+            .write_all(&self.record)
+            .map_err(|source| Error::Io { op, source })
+    }
+
+    fn put(&mut self, key: &Key, value: &[u8]) -> Result<(), Error> {
+        let record = self.record();
+        record.push(TAG_LEAF);
+        record.extend_from_slice(key.as_bytes());
+        record.extend_from_slice(&(value.len() as u16).to_le_bytes());
+        record.extend_from_slice(value);
+        self.count += 1;
+        self.emit()
+    }
+
+    fn remove(&mut self, key: &Key) -> Result<(), Error> {
+        let record = self.record();
+        record.push(TAG_REMOVED);
+        record.extend_from_slice(key.as_bytes());
+        self.count += 1;
+        self.emit()
+    }
+
+    /// Writes the trailer and returns the number of leaf records.
+    fn finish(mut self) -> Result<u64, Error> {
+        let count = self.count;
+        let record = self.record();
+        record.push(TAG_END);
+        record.extend_from_slice(&count.to_le_bytes());
+        self.emit()?;
+        let op = self.op;
+        let io_err = |source| Error::Io { op, source };
+        self.inner.write_all(&self.checksum.state).map_err(io_err)?;
+        self.inner.flush().map_err(io_err)?;
+        Ok(count)
+    }
+}
+
+enum Record {
+    Leaf(Key, Vec<u8>),
+    Removed(Key),
+}
+
+/// Reads the records of a snapshot or a delta, feeding them to the checksum.
+struct SnapshotReader<R> {
+    inner: BufReader<R>,
+    op: &'static str,
+    checksum: Checksum,
+    /// The bytes of the current record.
+    record: Vec<u8>,
+    count: u64,
+    prev_key: Option<Key>,
+}
+
+impl<R: Read> SnapshotReader<R> {
+    /// Starts reading, checking the magic and the version. The rest of the header is left to
+    /// the caller.
+    fn new(reader: R, magic: &[u8; 8], op: &'static str) -> Result<Self, Error> {
+        let mut reader = SnapshotReader {
+            inner: BufReader::new(reader),
+            op,
+            checksum: Checksum::new(),
+            record: Vec::with_capacity(1 + 32 + 2 + MAX_VALUE_SIZE),
+            count: 0,
+            prev_key: None,
+        };
+        if reader.read::<8>()? != *magic {
+            return Err(Error::MalformedSnapshot("unexpected magic"));
+        }
+        if reader.read::<1>()? != [VERSION] {
+            return Err(Error::MalformedSnapshot("unsupported version"));
+        }
+        Ok(reader)
+    }
+
+    /// Reads the next record, or the trailer if the records are over.
+    fn next_record(&mut self) -> Result<Option<Record>, Error> {
+        let tag = self.read::<1>()?[0];
+        if tag == TAG_END {
+            if u64::from_le_bytes(self.read()?) != self.count {
+                return Err(Error::MalformedSnapshot("the record count doesn't match"));
+            }
+            self.finish_record();
+            let mut checksum = [0; 32];
+            self.read_exact(&mut checksum)?;
+            if checksum != self.checksum.state {
+                return Err(Error::MalformedSnapshot("the checksum doesn't match"));
+            }
+            return Ok(None);
+        }
+
+        let key = Key::new(self.read()?);
+        let record = match tag {
+            TAG_LEAF => {
+                let size = u16::from_le_bytes(self.read()?) as usize;
+                if size > MAX_VALUE_SIZE {
+                    return Err(Error::MalformedSnapshot("the value is too large"));
+                }
+                let mut value = vec![0; size];
+                self.read_exact(&mut value)?;
+                self.record.extend_from_slice(&value);
+                Record::Leaf(key, value)
+            }
+            TAG_REMOVED => Record::Removed(key),
+            _ => return Err(Error::MalformedSnapshot("unknown record")),
+        };
+        self.finish_record();
+        // The order also rules out repeated keys, which would make the count meaningless.
+        if self.prev_key.is_some_and(|prev| prev >= key) {
+            return Err(Error::MalformedSnapshot("the records are out of order"));
+        }
+        self.prev_key = Some(key);
+        self.count += 1;
+        Ok(Some(record))
+    }
+
+    fn read<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut out = [0; N];
+        self.read_exact(&mut out)?;
+        self.record.extend_from_slice(&out);
+        Ok(out)
+    }
+
+    /// Reads bytes without adding them to the current record.
+    fn read_exact(&mut self, out: &mut [u8]) -> Result<(), Error> {
+        self.inner.read_exact(out).map_err(|source| {
+            if source.kind() == io::ErrorKind::UnexpectedEof {
+                Error::MalformedSnapshot("the snapshot is truncated")
+            } else {
+                Error::Io {
+                    op: self.op,
+                    source,
+                }
+            }
+        })
+    }
+
+    fn finish_record(&mut self) {
+        self.checksum.update(&self.record);
+        self.record.clear();
+    }
+}
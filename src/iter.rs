@@ -0,0 +1,219 @@
+use crate::error::{Errno, Error};
+use crate::{Key, MAX_VALUE_SIZE};
+use std::collections::VecDeque;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use urkel_sys as sys;
+
+/// An iterator over the leaves of a tree in ascending key order.
+///
+/// Borrows either the [`Database`](crate::Database) or the [`Transaction`](crate::Transaction)
+/// it was created from.
+///
+/// liburkel can only walk the tree forwards from its first leaf. [`Iter::seek`] and
+/// [`Iter::range`] thus still read and skip every leaf before the start of the range. Iterating
+/// backwards with [`Iterator::rev`] or [`DoubleEndedIterator::next_back`] is supported, but the
+/// first step from the back reads all the remaining leaves into memory.
+pub struct Iter<'a> {
+    iter: *mut sys::urkel_iter_t,
+    prefix: &'a Path,
+    start: Bound<Key>,
+    end: Bound<Key>,
+    done: bool,
+    back: Option<VecDeque<(Key, Vec<u8>)>>,
+}
+
+// `Iter` is neither `Send` nor `Sync`: an iterator created by `Transaction::iter` walks the
+// tree of the transaction which can still be read from the thread that owns it.
+
+impl<'a> Iter<'a> {
+    pub(crate) fn new(iter: *mut sys::urkel_iter_t, prefix: &'a Path) -> Self {
+        Iter {
+            iter,
+            prefix,
+            start: Bound::Unbounded,
+            end: Bound::Unbounded,
+            done: false,
+            back: None,
+        }
+    }
+
+    /// Skips all the leaves with keys less than `key`.
+    ///
+    /// The leaves are skipped one by one as the iterator advances, so this takes time
+    /// proportional to the number of keys before `key`.
+    pub fn seek(mut self, key: &Key) -> Self {
+        self.start = Bound::Included(*key);
+        self
+    }
+
+    /// Restricts the iterator to the leaves with keys within `range`.
+    ///
+    /// Like [`seek`](Self::seek), the leaves before the start of the range are read and
+    /// skipped one by one. The iterator stops at the first leaf past the end.
+    ///
+    /// ```
+    /// # use urkel::{Database, Key};
+    /// # let prefix_dir = tempfile::tempdir().unwrap();
+    /// # let db = Database::open(prefix_dir.path()).unwrap();
+    /// let mut tx = db.new_tx().unwrap();
+    /// for i in 0..10 {
+    ///     tx.insert(&Key::new([i; 32]), &[i]).unwrap();
+    /// }
+    /// let values = tx
+    ///     .iter()
+    ///     .unwrap()
+    ///     .range(Key::new([3; 32])..Key::new([6; 32]))
+    ///     .map(|leaf| leaf.map(|(_, value)| value[0]))
+    ///     .collect::<Result<Vec<_>, _>>()
+    ///     .unwrap();
+    /// assert_eq!(values, vec![3, 4, 5]);
+    /// ```
+    pub fn range(mut self, range: impl RangeBounds<Key>) -> Self {
+        self.start = range.start_bound().cloned();
+        self.end = range.end_bound().cloned();
+        self
+    }
+
+    /// Turns this into an iterator over the keys only, which doesn't allocate for the values.
+    pub fn keys(self) -> Keys<'a> {
+        Keys {
+            inner: self,
+            scratch: Vec::with_capacity(MAX_VALUE_SIZE),
+            back: None,
+        }
+    }
+
+    /// Reads the next leaf within the range, writing its value to `value` which must point to
+    /// at least `MAX_VALUE_SIZE` bytes. Returns the key and the size of the value.
+    fn next_leaf(&mut self, value: *mut u8) -> Option<Result<(Key, usize), Error>> {
+        while !self.done {
+            let mut key = Key::default();
+            let mut size = 0;
+            let ret = unsafe {
+                sys::urkel_iter_next(self.iter, key.as_mut_ptr(), value, &mut size as *mut usize)
+            };
+            if ret != 1 {
+                self.done = true;
+                let errno = Errno::fetch();
+                if errno.is_iter_end() {
+                    return None;
+                } else {
+                    return Some(Err(errno.into_error("iter_next", self.prefix)));
+                }
+            }
+
+            let after_start = match self.start {
+                Bound::Included(ref start) => key >= *start,
+                Bound::Excluded(ref start) => key > *start,
+                Bound::Unbounded => true,
+            };
+            if !after_start {
+                continue;
+            }
+            let before_end = match self.end {
+                Bound::Included(ref end) => key <= *end,
+                Bound::Excluded(ref end) => key < *end,
+                Bound::Unbounded => true,
+            };
+            if !before_end {
+                self.done = true;
+                return None;
+            }
+            return Some(Ok((key, size)));
+        }
+        None
+    }
+
+    fn next_entry(&mut self) -> Option<Result<(Key, Vec<u8>), Error>> {
+        let mut value = Vec::with_capacity(MAX_VALUE_SIZE);
+        self.next_leaf(value.as_mut_ptr()).map(|leaf| {
+            leaf.map(|(key, size)| {
+                unsafe {
+                    value.set_len(size);
+                }
+                (key, value)
+            })
+        })
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Result<(Key, Vec<u8>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.back {
+            Some(ref mut back) => back.pop_front().map(Ok),
+            None => self.next_entry(),
+        }
+    }
+}
+
+impl<'a> DoubleEndedIterator for Iter<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.back.is_none() {
+            let mut back = VecDeque::new();
+            while let Some(entry) = self.next_entry() {
+                match entry {
+                    Ok(entry) => back.push_back(entry),
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+            self.back = Some(back);
+        }
+        self.back.as_mut().and_then(|back| back.pop_back()).map(Ok)
+    }
+}
+
+impl<'a> Drop for Iter<'a> {
+    fn drop(&mut self) {
+        unsafe {
+            sys::urkel_iter_destroy(self.iter);
+        }
+    }
+}
+
+/// An iterator over the keys of a tree in ascending order.
+///
+/// Created by [`Iter::keys`].
+pub struct Keys<'a> {
+    inner: Iter<'a>,
+    scratch: Vec<u8>,
+    back: Option<VecDeque<Key>>,
+}
+
+impl<'a> Keys<'a> {
+    fn next_key(&mut self) -> Option<Result<Key, Error>> {
+        let scratch = self.scratch.as_mut_ptr();
+        self.inner
+            .next_leaf(scratch)
+            .map(|leaf| leaf.map(|(key, _)| key))
+    }
+}
+
+impl<'a> Iterator for Keys<'a> {
+    type Item = Result<Key, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.back {
+            Some(ref mut back) => back.pop_front().map(Ok),
+            None => self.next_key(),
+        }
+    }
+}
+
+impl<'a> DoubleEndedIterator for Keys<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.back.is_none() {
+            let mut back = VecDeque::new();
+            while let Some(key) = self.next_key() {
+                match key {
+                    Ok(key) => back.push_back(key),
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+            self.back = Some(back);
+        }
+        self.back.as_mut().and_then(|back| back.pop_back()).map(Ok)
+    }
+}
@@ -0,0 +1,112 @@
+use crate::error::ParseHexError;
+use core::fmt;
+use core::str::FromStr;
+#[cfg(feature = "native")]
+use {crate::error::Error, core::convert::TryFrom};
+
+/// A key in the tree.
+///
+/// Keys are always exactly 32 bytes long. Keys for identifiers of arbitrary length can be
+/// derived with [`Key::hash_of`].
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Key([u8; 32]);
+
+impl Key {
+    pub const fn new(bytes: [u8; 32]) -> Self {
+        Key(bytes)
+    }
+
+    /// Creates a key from a slice, returning `InvalidKeyLength` if it isn't 32 bytes long.
+    #[cfg(feature = "native")]
+    pub fn from_slice(bytes: &[u8]) -> Result<Self, Error> {
+        let mut key = [0; 32];
+        if bytes.len() != key.len() {
+            return Err(Error::InvalidKeyLength(bytes.len()));
+        }
+        key.copy_from_slice(bytes);
+        Ok(Key(key))
+    }
+
+    /// Derives a key by hashing `data` with BLAKE2b-256.
+    ///
+    /// ```
+    /// # use urkel::{blake2b_256, Key};
+    /// let key = Key::hash_of(b"account/alice");
+    /// assert_eq!(key.to_bytes(), blake2b_256(b"account/alice"));
+    /// ```
+    #[cfg(any(feature = "native", feature = "verify"))]
+    pub fn hash_of(data: &[u8]) -> Self {
+        Key(crate::util::blake2b_256(data))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn to_bytes(self) -> [u8; 32] {
+        self.0
+    }
+
+    #[cfg(feature = "native")]
+    pub(crate) fn as_ptr(&self) -> *const u8 {
+        self.0.as_ptr()
+    }
+
+    #[cfg(feature = "native")]
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.0.as_mut_ptr()
+    }
+}
+
+impl From<[u8; 32]> for Key {
+    fn from(bytes: [u8; 32]) -> Self {
+        Key(bytes)
+    }
+}
+
+impl From<Key> for [u8; 32] {
+    fn from(key: Key) -> Self {
+        key.0
+    }
+}
+
+#[cfg(feature = "native")]
+impl TryFrom<&[u8]> for Key {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        Key::from_slice(bytes)
+    }
+}
+
+impl AsRef<[u8]> for Key {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::LowerHex for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        crate::util::write_hex(f, &self.0)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(self, f)
+    }
+}
+
+impl fmt::Debug for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Key({:x})", self)
+    }
+}
+
+impl FromStr for Key {
+    type Err = ParseHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        crate::util::parse_hex_32(s).map(Key)
+    }
+}
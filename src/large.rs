@@ -0,0 +1,244 @@
+//! Values larger than [`MAX_VALUE_SIZE`], split into chunks that are stored and proven as
+//! ordinary leaves.
+//!
+//! A large value under the key `key` is laid out as:
+//!
+//! - a manifest leaf under `key` itself, holding the length of the value and its hash;
+//! - chunk `i` of the value under `Key::hash_of(key || i)`, with `i` as a little-endian `u32`.
+//!
+//! Every chunk is `MAX_VALUE_SIZE` bytes long, except the last one which may be shorter. An empty
+//! value has no chunks.
+
+use crate::multiproof::MultiProof;
+use crate::proof::{DecodeError, Proof, Reader, VerifyError};
+#[cfg(feature = "native")]
+use crate::{Error, Transaction};
+use crate::{Key, Root, MAX_VALUE_SIZE};
+use alloc::vec::Vec;
+
+/// Marks a leaf as the manifest of a large value.
+const MANIFEST_MAGIC: [u8; 4] = *b"ulv\0";
+
+/// The manifest of a large value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Manifest {
+    len: u64,
+    hash: [u8; 32],
+}
+
+impl Manifest {
+    const ENCODED_LEN: usize = MANIFEST_MAGIC.len() + 8 + 32;
+
+    fn of(value: &[u8]) -> Self {
+        Manifest {
+            len: value.len() as u64,
+            hash: crate::util::blake2b_256(value),
+        }
+    }
+
+    #[cfg(feature = "native")]
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(Self::ENCODED_LEN);
+        out.extend_from_slice(&MANIFEST_MAGIC);
+        out.extend_from_slice(&self.len.to_le_bytes());
+        out.extend_from_slice(&self.hash);
+        out
+    }
+
+    /// Returns `None` if `raw` is not a manifest.
+    fn decode(raw: &[u8]) -> Option<Self> {
+        if raw.len() != Self::ENCODED_LEN || raw[..MANIFEST_MAGIC.len()] != MANIFEST_MAGIC {
+            return None;
+        }
+        let mut len = [0; 8];
+        len.copy_from_slice(&raw[4..12]);
+        let mut hash = [0; 32];
+        hash.copy_from_slice(&raw[12..]);
+        Some(Manifest {
+            len: u64::from_le_bytes(len),
+            hash,
+        })
+    }
+
+    /// Decodes a manifest read from the store, which may be corrupted: returns `None` as well
+    /// if its chunks can't all be addressed.
+    #[cfg(feature = "native")]
+    fn decode_stored(raw: &[u8]) -> Option<Self> {
+        Manifest::decode(raw).filter(|manifest| manifest.chunk_count() <= u32::MAX as u64)
+    }
+
+    fn chunk_count(&self) -> u64 {
+        self.len.div_ceil(MAX_VALUE_SIZE as u64)
+    }
+
+    /// Returns the keys of the chunks. The count must have been checked to be sane.
+    fn chunk_keys(&self, key: &Key) -> Vec<Key> {
+        (0..self.chunk_count() as u32)
+            .map(|index| chunk_key(key, index))
+            .collect()
+    }
+
+    /// Checks that `value` is the value described by the manifest.
+    fn matches(&self, value: &[u8]) -> bool {
+        *self == Manifest::of(value)
+    }
+}
+
+fn chunk_key(key: &Key, index: u32) -> Key {
+    let mut data = [0; 36];
+    data[..32].copy_from_slice(key.as_bytes());
+    data[32..].copy_from_slice(&index.to_le_bytes());
+    Key::hash_of(&data)
+}
+
+/// A proof of a large value stored with [`Transaction::put_large`], or of its absence.
+///
+/// Consists of the proof of the manifest and a multiproof of all the chunks. The encoding is
+/// the length of the encoded manifest proof as a little-endian `u32`, the manifest proof, then
+/// the multiproof.
+///
+/// [`Transaction::put_large`]: crate::Transaction::put_large
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LargeValueProof {
+    manifest: Proof,
+    chunks: MultiProof,
+}
+
+impl LargeValueProof {
+    pub fn manifest(&self) -> &Proof {
+        &self.manifest
+    }
+
+    pub fn chunks(&self) -> &MultiProof {
+        &self.chunks
+    }
+
+    /// Verifies the proof for `key` against `root` and reassembles the value, returning `None`
+    /// if the key is proven to be absent.
+    ///
+    /// Fails with `InvalidProof` if the manifest and the chunks don't add up to a large value.
+    pub fn verify(&self, key: &Key, root: Root) -> Result<Option<Vec<u8>>, VerifyError> {
+        let manifest = match self.manifest.verify(key, root)? {
+            Some(manifest) => Manifest::decode(&manifest).ok_or(VerifyError::InvalidProof)?,
+            None if self.chunks.is_empty() => return Ok(None),
+            None => return Err(VerifyError::InvalidProof),
+        };
+        if manifest.chunk_count() != self.chunks.len() as u64 {
+            return Err(VerifyError::InvalidProof);
+        }
+        let mut value = Vec::with_capacity(manifest.len as usize);
+        for chunk in self.chunks.verify(&manifest.chunk_keys(key), root)? {
+            value.extend(chunk.ok_or(VerifyError::InvalidProof)?);
+        }
+        if !manifest.matches(&value) {
+            return Err(VerifyError::InvalidProof);
+        }
+        Ok(Some(value))
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let manifest = self.manifest.encode();
+        let mut out = Vec::new();
+        out.extend_from_slice(&(manifest.len() as u32).to_le_bytes());
+        out.extend_from_slice(&manifest);
+        out.extend_from_slice(&self.chunks.encode());
+        out
+    }
+
+    pub fn decode(raw: &[u8]) -> Result<LargeValueProof, DecodeError> {
+        let mut r = Reader::new(raw);
+        let manifest_len = r.u32()? as usize;
+        let manifest = Proof::decode(r.bytes(manifest_len)?)?;
+        let chunks = MultiProof::decode(r.bytes(raw.len() - 4 - manifest_len)?)?;
+        Ok(LargeValueProof { manifest, chunks })
+    }
+}
+
+#[cfg(feature = "native")]
+impl Transaction<'_> {
+    /// Inserts a value of any size under `key`, splitting it into chunks.
+    ///
+    /// Replaces the large value previously stored under `key`, if any. Either all of the
+    /// leaves are written or none of them.
+    pub fn put_large(&mut self, key: &Key, value: &[u8]) -> Result<(), Error> {
+        if value.len() as u64 > u32::MAX as u64 * MAX_VALUE_SIZE as u64 {
+            return Err(Error::ValueTooLarge);
+        }
+        self.atomically(|tx| {
+            let manifest = Manifest::of(value);
+            let stale = tx.get(key)?.and_then(|old| Manifest::decode_stored(&old));
+            if let Some(stale) = stale {
+                for index in manifest.chunk_count()..stale.chunk_count() {
+                    tx.remove(&chunk_key(key, index as u32))?;
+                }
+            }
+            for (index, chunk) in value.chunks(MAX_VALUE_SIZE).enumerate() {
+                tx.insert(&chunk_key(key, index as u32), chunk)?;
+            }
+            tx.insert(key, &manifest.encode())
+        })
+    }
+
+    /// Reads and reassembles the large value under `key`.
+    ///
+    /// Fails with `InvalidLargeValue` if the value under `key` was not stored with
+    /// [`put_large`](Self::put_large) or its chunks are missing or don't match.
+    pub fn get_large(&self, key: &Key) -> Result<Option<Vec<u8>>, Error> {
+        let manifest = match self.get(key)? {
+            Some(manifest) => self.decode_manifest(key, &manifest)?,
+            None => return Ok(None),
+        };
+        // The length isn't trusted for preallocation, the store may be corrupted.
+        let mut value = Vec::new();
+        for chunk_key in manifest.chunk_keys(key) {
+            let chunk = self.get(&chunk_key)?;
+            value.extend(chunk.ok_or(Error::InvalidLargeValue(*key))?);
+        }
+        if !manifest.matches(&value) {
+            return Err(Error::InvalidLargeValue(*key));
+        }
+        Ok(Some(value))
+    }
+
+    /// Removes the large value under `key` along with all of its chunks.
+    pub fn remove_large(&mut self, key: &Key) -> Result<(), Error> {
+        let manifest = match self.get(key)? {
+            Some(manifest) => self.decode_manifest(key, &manifest)?,
+            None => return Err(Error::NotFound),
+        };
+        self.atomically(|tx| {
+            for chunk_key in manifest.chunk_keys(key) {
+                tx.remove(&chunk_key)?;
+            }
+            tx.remove(key)
+        })
+    }
+
+    /// Proves the large value under `key`, or its absence, against the current root of the
+    /// transaction.
+    pub fn prove_large(&self, key: &Key) -> Result<LargeValueProof, Error> {
+        let chunk_keys = match self.get(key)? {
+            Some(manifest) => self.decode_manifest(key, &manifest)?.chunk_keys(key),
+            None => Vec::new(),
+        };
+        Ok(LargeValueProof {
+            manifest: self.prove(key)?,
+            chunks: self.prove_many(&chunk_keys)?,
+        })
+    }
+
+    fn decode_manifest(&self, key: &Key, raw: &[u8]) -> Result<Manifest, Error> {
+        Manifest::decode_stored(raw).ok_or(Error::InvalidLargeValue(*key))
+    }
+
+    /// Runs `f`, undoing its changes if it fails.
+    fn atomically(&mut self, f: impl FnOnce(&mut Self) -> Result<(), Error>) -> Result<(), Error> {
+        let savepoint = self.savepoint();
+        let result = f(self);
+        if result.is_err() {
+            self.rollback_to(savepoint)?;
+        }
+        self.release(savepoint)?;
+        result
+    }
+}
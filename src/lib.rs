@@ -1,12 +1,67 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "async")]
+mod async_db;
+#[cfg(feature = "native")]
+mod batch;
+#[cfg(feature = "native")]
+mod blob;
+#[cfg(feature = "native")]
+mod compact;
+#[cfg(feature = "native")]
 mod db;
+#[cfg(feature = "native")]
+mod diff;
 mod error;
+#[cfg(feature = "native")]
+mod export;
+#[cfg(feature = "native")]
+mod iter;
+mod key;
+#[cfg(any(feature = "native", feature = "verify"))]
+mod large;
+#[cfg(feature = "native")]
+mod lock;
+mod multiproof;
+#[cfg(feature = "native")]
+mod options;
 mod proof;
+mod root;
+#[cfg(feature = "serde")]
+mod serde_impl;
 mod util;
 
-pub use db::{Database, Iter, Key, Transaction, MAX_VALUE_SIZE};
+#[cfg(feature = "async")]
+pub use async_db::{AsyncDatabase, AsyncIter, AsyncTransaction};
+#[cfg(feature = "native")]
+pub use batch::{BatchOp, WriteBatch};
+#[cfg(feature = "native")]
+pub use blob::BlobStore;
+#[cfg(feature = "native")]
+pub use compact::CompactReport;
+#[cfg(feature = "native")]
+pub use db::{ChangeKind, CommitReport, Database, SavepointId, Snapshot, Transaction};
+#[cfg(feature = "native")]
+pub use diff::{Change, Diff};
+#[cfg(feature = "native")]
 pub use error::Error;
-pub use proof::{Proof, VerifyError};
+pub use error::ParseHexError;
+#[cfg(feature = "native")]
+pub use iter::{Iter, Keys};
+pub use key::Key;
+#[cfg(any(feature = "native", feature = "verify"))]
+pub use large::LargeValueProof;
+pub use multiproof::MultiProof;
+#[cfg(feature = "native")]
+pub use options::DatabaseOptions;
+pub use proof::{Bits, DecodeError, Proof, ProofNode, ProofType, VerifyError};
+pub use root::Root;
+#[cfg(any(feature = "native", feature = "verify"))]
 pub use util::blake2b_256;
 
-#[cfg(test)]
+pub const MAX_VALUE_SIZE: usize = 1024;
+
+#[cfg(all(test, feature = "native"))]
 mod tests;
@@ -0,0 +1,122 @@
+use crate::Error;
+use fs2::FileExt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// The name of the lock file in the prefix directory.
+pub(crate) const LOCK_FILE: &str = "LOCK";
+
+/// The name of the commit lock file in the prefix directory, see [`CommitLock`].
+pub(crate) const COMMIT_LOCK_FILE: &str = "COMMIT";
+
+/// An exclusive advisory lock on a prefix, held for as long as the value is alive.
+///
+/// The lock file records the pid of the process holding it, to be reported to whoever fails to
+/// take the lock. The file itself is left in place when the lock is released.
+#[derive(Debug)]
+pub(crate) struct PrefixLock {
+    file: File,
+}
+
+impl PrefixLock {
+    /// Takes the lock on `prefix`, creating the directory if it doesn't exist.
+    ///
+    /// Fails with `Locked` if the lock is held, whether by another process or by another handle
+    /// in this process.
+    pub(crate) fn acquire(prefix: &Path, op: &'static str) -> Result<Self, Error> {
+        let io_error = |source| Error::Io { op, source };
+        fs::create_dir_all(prefix).map_err(io_error)?;
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(prefix.join(LOCK_FILE))
+            .map_err(io_error)?;
+        match file.try_lock_exclusive() {
+            // `WouldBlock` on Unix, the error of the platform otherwise.
+            Err(err) if err.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {
+                return Err(Error::Locked {
+                    holder_pid: read_pid(&mut file),
+                })
+            }
+            result => result.map_err(io_error)?,
+        }
+        file.set_len(0).map_err(io_error)?;
+        write!(file, "{}", std::process::id()).map_err(io_error)?;
+        file.sync_all().map_err(io_error)?;
+        Ok(PrefixLock { file })
+    }
+}
+
+impl Drop for PrefixLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// Keeps the store from being opened while it is written to.
+///
+/// liburkel repairs a store whose last write is incomplete when it opens it, so a reader opening
+/// the store in the middle of a commit could cut off the data of the writer. The writer holds
+/// the lock exclusively while it writes to the store, and the readers hold it shared while they
+/// open it, so that a reader always finds the store consistent.
+#[derive(Debug)]
+pub(crate) struct CommitLock {
+    file: File,
+    /// Serializes the writes made by this process, which share the lock on the file.
+    local: Mutex<()>,
+}
+
+impl CommitLock {
+    /// Opens the commit lock file of `prefix`, creating it if it doesn't exist.
+    pub(crate) fn open(prefix: &Path, op: &'static str) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(prefix.join(COMMIT_LOCK_FILE))
+            .map_err(|source| Error::Io { op, source })?;
+        Ok(CommitLock {
+            file,
+            local: Mutex::new(()),
+        })
+    }
+
+    /// Waits for the lock, exclusively to write to the store or shared to open it.
+    pub(crate) fn lock(&self, exclusive: bool, op: &'static str) -> Result<CommitGuard<'_>, Error> {
+        let local = self.local.lock().unwrap_or_else(PoisonError::into_inner);
+        let locked = if exclusive {
+            self.file.lock_exclusive()
+        } else {
+            self.file.lock_shared()
+        };
+        locked.map_err(|source| Error::Io { op, source })?;
+        Ok(CommitGuard {
+            file: &self.file,
+            _local: local,
+        })
+    }
+}
+
+/// Holds a [`CommitLock`] until dropped.
+pub(crate) struct CommitGuard<'a> {
+    file: &'a File,
+    _local: MutexGuard<'a, ()>,
+}
+
+impl Drop for CommitGuard<'_> {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(self.file);
+    }
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut pid = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut pid).ok()?;
+    pid.trim().parse().ok()
+}
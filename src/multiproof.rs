@@ -0,0 +1,131 @@
+use crate::proof::{DecodeError, DecodedProof, Proof, Reader};
+#[cfg(any(feature = "native", feature = "verify"))]
+use crate::{proof::VerifyError, Key, Root};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+const VERSION: u8 = 0;
+
+/// Proofs for several keys against the same root.
+///
+/// Keys that are close to each other in the tree share most of the nodes on their paths, so a
+/// multiproof stores every distinct node hash only once and refers to it by index.
+///
+/// The encoding is, with all integers little-endian:
+///
+/// ```text
+/// version:     u8           currently 0
+/// hash_count:  u32
+/// hashes:      hash_count * [u8; 32]
+/// proof_count: u32
+/// proofs:      proof_count * proof
+/// ```
+///
+/// where each proof is laid out as a single-key proof produced by liburkel, except that every
+/// node hash is replaced with a `u32` index into `hashes`. The proofs go in the order of the keys
+/// they were generated for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MultiProof {
+    proofs: Vec<DecodedProof>,
+}
+
+impl MultiProof {
+    /// Combines single-key proofs generated against the same root.
+    #[cfg(feature = "native")]
+    pub(crate) fn from_proofs(proofs: Vec<Proof>) -> MultiProof {
+        let proofs = proofs.into_iter().map(Proof::into_decoded).collect();
+        MultiProof { proofs }
+    }
+
+    /// Returns the number of keys covered by this proof.
+    pub fn len(&self) -> usize {
+        self.proofs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.proofs.is_empty()
+    }
+
+    /// Splits this multiproof back into the single-key proofs it was built from.
+    pub fn to_proofs(&self) -> Vec<Proof> {
+        self.proofs
+            .iter()
+            .map(|proof| Proof::from_decoded(proof.clone()))
+            .collect()
+    }
+
+    /// Verifies the proof for each of `keys` against `root`.
+    ///
+    /// The keys must be given in the same order as they were passed to `prove_many`. Returns the
+    /// value of each key, or `None` if the key is proven to be absent.
+    #[cfg(any(feature = "native", feature = "verify"))]
+    pub fn verify(&self, keys: &[Key], root: Root) -> Result<Vec<Option<Vec<u8>>>, VerifyError> {
+        if keys.len() != self.proofs.len() {
+            return Err(VerifyError::InvalidProof);
+        }
+        keys.iter()
+            .zip(self.to_proofs())
+            .map(|(key, proof)| proof.verify(key, root))
+            .collect()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut hashes = Vec::new();
+        let mut index = BTreeMap::new();
+        for node in self.proofs.iter().flat_map(|proof| &proof.nodes) {
+            index.entry(node.hash).or_insert_with(|| {
+                hashes.push(node.hash);
+                hashes.len() as u32 - 1
+            });
+        }
+
+        let mut out = alloc::vec![VERSION];
+        out.extend_from_slice(&(hashes.len() as u32).to_le_bytes());
+        for hash in &hashes {
+            out.extend_from_slice(hash);
+        }
+        out.extend_from_slice(&(self.proofs.len() as u32).to_le_bytes());
+        for proof in &self.proofs {
+            proof.encode_with(&mut out, |out, hash| {
+                out.extend_from_slice(&index[hash].to_le_bytes())
+            });
+        }
+        out
+    }
+
+    /// Decodes a multiproof, checking that it is well-formed.
+    ///
+    /// Like [`Proof::decode`], only the canonical encoding is accepted: encoding the multiproof
+    /// gives back exactly `raw`. In particular, every hash must be referenced and appear once,
+    /// in the order of first use.
+    pub fn decode(raw: &[u8]) -> Result<MultiProof, DecodeError> {
+        let mut r = Reader::new(raw);
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let hash_count = r.u32()? as usize;
+        // Don't trust the count for preallocation, each hash must be present in the input.
+        let mut hashes = Vec::new();
+        for _ in 0..hash_count {
+            hashes.push(r.array()?);
+        }
+        let proof_count = r.u32()?;
+        let mut proofs = Vec::new();
+        for _ in 0..proof_count {
+            proofs.push(DecodedProof::decode_with(&mut r, |r| {
+                let index = r.u32()?;
+                hashes
+                    .get(index as usize)
+                    .copied()
+                    .ok_or(DecodeError::InvalidHashIndex(index))
+            })?);
+        }
+        r.finish()?;
+        let multiproof = MultiProof { proofs };
+        if multiproof.encode() != raw {
+            return Err(DecodeError::NonCanonical);
+        }
+        Ok(multiproof)
+    }
+}
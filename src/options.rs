@@ -0,0 +1,161 @@
+use crate::blob::BLOBS_DIR;
+use crate::lock::{PrefixLock, COMMIT_LOCK_FILE, LOCK_FILE};
+use crate::{Database, Error, Root};
+use std::io;
+use std::path::Path;
+
+/// Options controlling how a [`Database`] is opened.
+///
+/// By default a store is created if it doesn't exist and opened as is otherwise, the same as
+/// [`Database::open`] does.
+///
+/// Unless opened read-only, the database holds an exclusive lock on the prefix, so that no other
+/// handle, in this or another process, can open it for writing at the same time. Opening a
+/// locked prefix fails with `Locked`.
+///
+/// ```
+/// # use urkel::{DatabaseOptions, Error};
+/// # let prefix_dir = tempfile::tempdir().unwrap();
+/// // Refuse to start from an empty datadir, e.g. because of a typo in the path.
+/// let err = DatabaseOptions::new()
+///     .create_if_missing(false)
+///     .open(prefix_dir.path().join("chain"))
+///     .unwrap_err();
+/// assert!(matches!(err, Error::DoesNotExist { .. }));
+/// ```
+#[derive(Clone, Debug)]
+pub struct DatabaseOptions {
+    create_if_missing: bool,
+    error_if_exists: bool,
+    expected_root: Option<Root>,
+    read_only: bool,
+    retention_limit: Option<usize>,
+}
+
+impl Default for DatabaseOptions {
+    fn default() -> Self {
+        DatabaseOptions {
+            create_if_missing: true,
+            error_if_exists: false,
+            expected_root: None,
+            read_only: false,
+            retention_limit: None,
+        }
+    }
+}
+
+impl DatabaseOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether to create a new store if there is none at the prefix. Otherwise opening fails
+    /// with `DoesNotExist`. Defaults to `true`.
+    pub fn create_if_missing(&mut self, create_if_missing: bool) -> &mut Self {
+        self.create_if_missing = create_if_missing;
+        self
+    }
+
+    /// Whether opening an existing store fails with `AlreadyExists`. Defaults to `false`.
+    pub fn error_if_exists(&mut self, error_if_exists: bool) -> &mut Self {
+        self.error_if_exists = error_if_exists;
+        self
+    }
+
+    /// Refuses to open a store whose persisted root isn't `root`, failing with `RootMismatch`.
+    ///
+    /// A newly created store has the root [`Root::EMPTY`]. If there is no store at the prefix
+    /// and `root` is another one, nothing is created.
+    pub fn expected_root(&mut self, root: Root) -> &mut Self {
+        self.expected_root = Some(root);
+        self
+    }
+
+    /// Whether to open the store for reading only, see [`Database::open_read_only`]. A store
+    /// is never created in this mode. Defaults to `false`.
+    pub fn read_only(&mut self, read_only: bool) -> &mut Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Sets a retention policy keeping only the last `count` committed roots, applied
+    /// automatically on commit.
+    ///
+    /// Every commit through the returned handle is recorded. Once the handle has seen twice as
+    /// many roots, counting the one it was opened at, the store is compacted with
+    /// [`Database::compact`] down to the last `count`. Only those last roots are remembered in
+    /// the meantime.
+    ///
+    /// [`Database::insert`], [`Database::remove`] and [`Database::set_head`] compact the store
+    /// right after their commit, and fail with the error of the compaction if it fails, even
+    /// though their own write is persisted. A [`Transaction`](crate::Transaction) borrows the
+    /// database, which can't be compacted until the borrow ends, so a compaction due after a
+    /// transaction commit is deferred to the next direct write, or to
+    /// [`Database::apply_retention`] if that comes first.
+    ///
+    /// Only the roots committed through the returned handle are counted. The roots committed
+    /// before it was opened are older than those, so the first compaction drops them.
+    ///
+    /// By default there is no retention policy.
+    pub fn retention_limit(&mut self, count: usize) -> &mut Self {
+        self.retention_limit = Some(count);
+        self
+    }
+
+    pub fn open(&self, prefix: impl AsRef<Path>) -> Result<Database, Error> {
+        let prefix = prefix.as_ref();
+        let exists = store_exists(prefix).map_err(|source| Error::Io { op: "open", source })?;
+        if exists && self.error_if_exists {
+            return Err(Error::AlreadyExists {
+                path: prefix.to_path_buf(),
+            });
+        }
+        if !exists && (!self.create_if_missing || self.read_only) {
+            return Err(Error::DoesNotExist {
+                path: prefix.to_path_buf(),
+            });
+        }
+        if !exists {
+            // Don't create a store only to reject its empty root.
+            if let Some(expected) = self.expected_root.filter(|root| *root != Root::EMPTY) {
+                return Err(Error::RootMismatch {
+                    expected,
+                    actual: Root::EMPTY,
+                });
+            }
+        }
+
+        let lock = if self.read_only {
+            None
+        } else {
+            Some(PrefixLock::acquire(prefix, "open")?)
+        };
+        let mut db = Database::open_store(prefix, lock)?;
+        db.set_retention_limit(self.retention_limit);
+        if let Some(expected) = self.expected_root {
+            let actual = db.root();
+            if actual != expected {
+                return Err(Error::RootMismatch { expected, actual });
+            }
+        }
+        Ok(db)
+    }
+}
+
+/// Checks whether there is a store at `prefix`, i.e. the directory holds anything besides the
+/// lock files and the blob store.
+fn store_exists(prefix: &Path) -> io::Result<bool> {
+    match prefix.read_dir() {
+        Ok(entries) => {
+            for entry in entries {
+                let name = entry?.file_name();
+                if name != LOCK_FILE && name != COMMIT_LOCK_FILE && name != BLOBS_DIR {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
@@ -1,23 +1,154 @@
+#[cfg(feature = "native")]
 use crate::error::Errno;
+use crate::util::Hex;
+#[cfg(any(feature = "native", feature = "verify"))]
+use crate::Root;
 use crate::{Key, MAX_VALUE_SIZE};
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "native")]
 use urkel_sys as sys;
 
-#[derive(Debug)]
+/// A proof of presence or absence of a key in the tree at some root.
+///
+/// The encoding used by liburkel is, with all integers little-endian:
+///
+/// ```text
+/// field:  u16          type << 14 | depth
+/// count:  u16          number of nodes
+/// bitmap: (count + 7) / 8 bytes, bit i (MSB first) set if node i carries a prefix
+/// nodes:  count * ([prefix bits] hash:[u8; 32])
+/// then, depending on the type:
+///   DEADEND    nothing
+///   SHORT      prefix bits, left:[u8; 32], right:[u8; 32]
+///   COLLISION  key:[u8; 32], hash:[u8; 32]
+///   EXISTS     size:u16, value:[u8; size]
+/// ```
+///
+/// where prefix bits are a bit count (one byte, or two big-endian bytes with the top bit set if
+/// the count is 128 or more) followed by the bits themselves, MSB first.
+#[derive(Clone, PartialEq, Eq)]
 pub struct Proof {
+    /// The structure of the proof, or the bytes of a proof created with `new_unchecked` that
+    /// could not be decoded.
+    decoded: Result<DecodedProof, Malformed>,
+}
+
+#[derive(Clone, PartialEq, Eq)]
+struct Malformed {
     raw: Vec<u8>,
+    error: DecodeError,
 }
 
 impl Proof {
+    #[cfg(feature = "native")]
     pub(crate) unsafe fn from_ptr(proof_raw: *mut u8, proof_len: usize) -> Self {
         let raw = std::slice::from_raw_parts(proof_raw, proof_len);
-        Self { raw: raw.to_vec() }
+        Proof::new_unchecked(raw.to_vec())
+    }
+
+    pub(crate) fn from_decoded(decoded: DecodedProof) -> Self {
+        Proof {
+            decoded: Ok(decoded),
+        }
+    }
+
+    /// Decodes a proof, checking that it is well-formed.
+    ///
+    /// Only the structure is checked, whether the proof is valid for some key and root is
+    /// determined by [`Proof::verify`]. Encoding the proof gives back exactly `raw`.
+    pub fn decode(raw: &[u8]) -> Result<Proof, DecodeError> {
+        let decoded = DecodedProof::decode(raw)?;
+        // The decoder skips the padding of the bitmap, which must be zero to round-trip.
+        if decoded.encode() != raw {
+            return Err(DecodeError::NonCanonical);
+        }
+        Ok(Proof::from_decoded(decoded))
     }
 
+    /// Creates a proof from its encoding without checking it.
+    ///
+    /// A proof that isn't well-formed is kept as is. It has no structure, fails verification
+    /// with `InvalidProof`, and [`Proof::decode_error`] tells what is wrong with it.
     pub fn new_unchecked(raw: Vec<u8>) -> Proof {
-        Proof { raw }
+        match Proof::decode(&raw) {
+            Ok(proof) => proof,
+            Err(error) => Proof {
+                decoded: Err(Malformed { raw, error }),
+            },
+        }
+    }
+
+    /// Returns the structure of a proof produced by liburkel.
+    #[cfg(feature = "native")]
+    pub(crate) fn into_decoded(self) -> DecodedProof {
+        match self.decoded {
+            Ok(decoded) => decoded,
+            Err(_) => panic!("liburkel produced a malformed proof"),
+        }
     }
 
-    pub fn verify(&self, key: &Key, root: [u8; 32]) -> Result<Option<Vec<u8>>, VerifyError> {
+    /// Returns `true` if the proof was created with [`Proof::new_unchecked`] from bytes that
+    /// are not a well-formed proof.
+    pub fn is_malformed(&self) -> bool {
+        self.decoded.is_err()
+    }
+
+    /// Returns why the proof could not be decoded, or `None` if it is well-formed.
+    pub fn decode_error(&self) -> Option<&DecodeError> {
+        self.decoded
+            .as_ref()
+            .err()
+            .map(|malformed| &malformed.error)
+    }
+
+    /// Returns the depth of the node the path to the key ends at, or `None` if the proof is
+    /// malformed.
+    pub fn depth(&self) -> Option<u16> {
+        self.decoded.as_ref().ok().map(|decoded| decoded.depth)
+    }
+
+    /// Returns the sibling nodes on the path from the root down to the key, or `None` if the
+    /// proof is malformed.
+    pub fn nodes(&self) -> Option<&[ProofNode]> {
+        self.decoded.as_ref().ok().map(|decoded| &decoded.nodes[..])
+    }
+
+    /// Returns what the path to the key ends at, or `None` if the proof is malformed.
+    pub fn proof_type(&self) -> Option<&ProofType> {
+        self.decoded.as_ref().ok().map(|decoded| &decoded.ty)
+    }
+
+    /// Returns the value of the key if this is a well-formed proof of existence.
+    pub fn value(&self) -> Option<&[u8]> {
+        match self.proof_type()? {
+            ProofType::Exists { value } => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Verifies the proof for `key` against `root`, returning the value of the key or `None` if
+    /// the key is proven to be absent.
+    ///
+    /// Uses liburkel if the `native` feature is enabled and the pure-Rust verifier otherwise.
+    #[cfg(any(feature = "native", feature = "verify"))]
+    pub fn verify(&self, key: &Key, root: Root) -> Result<Option<Vec<u8>>, VerifyError> {
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "native")] {
+                self.verify_native(key, root)
+            } else {
+                self.verify_pure(key, root)
+            }
+        }
+    }
+
+    #[cfg(feature = "native")]
+    pub(crate) fn verify_native(
+        &self,
+        key: &Key,
+        root: Root,
+    ) -> Result<Option<Vec<u8>>, VerifyError> {
+        let raw = self.encode();
         let mut exists = 0;
         let mut v = Vec::with_capacity(MAX_VALUE_SIZE);
         let mut v_len = 0usize;
@@ -27,8 +158,8 @@ impl Proof {
                 &mut exists as *mut _,
                 v.as_mut_ptr(),
                 &mut v_len as *mut usize,
-                self.raw.as_ptr(),
-                self.raw.len(),
+                raw.as_ptr(),
+                raw.len(),
                 key.as_ptr(),
                 root.as_ptr(),
             )
@@ -47,31 +178,86 @@ impl Proof {
         })
     }
 
+    /// Verifies the proof like [`Proof::verify`], always using the pure-Rust verifier.
+    ///
+    /// The result is the same as with liburkel, including the error variants.
+    #[cfg(feature = "verify")]
+    pub fn verify_pure(&self, key: &Key, root: Root) -> Result<Option<Vec<u8>>, VerifyError> {
+        match &self.decoded {
+            Ok(decoded) => decoded.verify(key, root),
+            // liburkel ignores the padding of the bitmap, so non-canonical proofs can still be
+            // valid.
+            Err(malformed) => DecodedProof::decode(&malformed.raw)
+                .map_err(|_| VerifyError::InvalidProof)?
+                .verify(key, root),
+        }
+    }
+
+    /// Encodes the proof.
+    pub fn encode(&self) -> Vec<u8> {
+        match &self.decoded {
+            Ok(decoded) => decoded.encode(),
+            Err(malformed) => malformed.raw.clone(),
+        }
+    }
+
     pub fn into_inner(self) -> Vec<u8> {
-        self.raw
+        match self.decoded {
+            Ok(decoded) => decoded.encode(),
+            Err(malformed) => malformed.raw,
+        }
+    }
+}
+
+impl fmt::Debug for Proof {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.decoded {
+            Ok(decoded) => f
+                .debug_struct("Proof")
+                .field("depth", &decoded.depth)
+                .field("nodes", &decoded.nodes)
+                .field("type", &decoded.ty)
+                .finish(),
+            Err(malformed) => f
+                .debug_tuple("Proof::Malformed")
+                .field(&Hex(&malformed.raw))
+                .finish(),
+        }
     }
 }
 
-#[derive(Clone, Debug, thiserror::Error)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum VerifyError {
-    #[error("Computed hash did not match expected hash")]
     HashMismatch,
-    #[error("Expected different key")]
     SameKey,
-    #[error("Expected different prefix bits")]
     SamePath,
-    #[error("Depth went negative")]
     NegativeDepth,
-    #[error("Prefix bits do not match key")]
     PathMismatch,
-    #[error("Depth is not satisfied by proof nodes")]
     TooDeep,
-    #[error("The proof is invalid")]
     InvalidProof,
-    #[error("Unknown error occured")]
     Unknown,
 }
 
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            VerifyError::HashMismatch => "Computed hash did not match expected hash",
+            VerifyError::SameKey => "Expected different key",
+            VerifyError::SamePath => "Expected different prefix bits",
+            VerifyError::NegativeDepth => "Depth went negative",
+            VerifyError::PathMismatch => "Prefix bits do not match key",
+            VerifyError::TooDeep => "Depth is not satisfied by proof nodes",
+            VerifyError::InvalidProof => "The proof is invalid",
+            VerifyError::Unknown => "Unknown error occured",
+        })
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for VerifyError {}
+
+#[cfg(feature = "native")]
 impl VerifyError {
     fn from_errno() -> VerifyError {
         match Errno::fetch().into_raw() {
@@ -89,3 +275,486 @@ impl VerifyError {
         }
     }
 }
+
+/// The maximum depth of the tree, i.e. the number of bits in a key.
+pub(crate) const KEY_BITS: usize = 256;
+
+/// A run of key bits stored as the prefix of an internal node.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Bits {
+    size: usize,
+    data: Vec<u8>,
+}
+
+impl Bits {
+    /// Returns the number of bits.
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Returns the bit at `index`, counting from the most significant bit of the first byte.
+    ///
+    /// Panics if `index` is out of range.
+    pub fn get(&self, index: usize) -> bool {
+        assert!(index < self.size, "bit index out of range");
+        self.data[index / 8] & (0x80 >> (index % 8)) != 0
+    }
+
+    /// Returns the bits packed into bytes, MSB first.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the size of the encoding of these bits in bytes.
+    fn encoded_len(&self) -> usize {
+        if self.size >= 0x80 {
+            2 + self.data.len()
+        } else {
+            1 + self.data.len()
+        }
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        if self.size >= 0x80 {
+            out.push(0x80 | (self.size >> 8) as u8);
+        }
+        out.push(self.size as u8);
+        out.extend_from_slice(&self.data);
+    }
+
+    fn decode(r: &mut Reader) -> Result<Bits, DecodeError> {
+        let mut size = r.u8()? as usize;
+        if size & 0x80 != 0 {
+            size = (size & 0x7f) << 8 | r.u8()? as usize;
+        }
+        if size > KEY_BITS {
+            return Err(DecodeError::InvalidPrefixSize(size));
+        }
+        let data = r.bytes(size.div_ceil(8))?.to_vec();
+        Ok(Bits { size, data })
+    }
+}
+
+impl fmt::Debug for Bits {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Bits(")?;
+        for i in 0..self.size {
+            f.write_str(if self.get(i) { "1" } else { "0" })?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// A sibling hash met on the way from the root down to the key.
+///
+/// If the internal node the sibling belongs to skips some bits of the key, they are given in
+/// `prefix`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ProofNode {
+    pub prefix: Option<Bits>,
+    pub hash: [u8; 32],
+}
+
+impl fmt::Debug for ProofNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProofNode")
+            .field("prefix", &self.prefix)
+            .field("hash", &Hex(&self.hash))
+            .finish()
+    }
+}
+
+/// What the path to the key ends at.
+#[derive(Clone, PartialEq, Eq)]
+pub enum ProofType {
+    /// An empty subtree, so the key is absent.
+    Deadend,
+    /// An internal node whose prefix diverges from the key, so the key is absent.
+    Short {
+        prefix: Bits,
+        left: [u8; 32],
+        right: [u8; 32],
+    },
+    /// A leaf holding a different key, so the key is absent. `hash` is the hash of its value.
+    Collision { key: Key, hash: [u8; 32] },
+    /// The leaf of the key itself.
+    Exists { value: Vec<u8> },
+}
+
+impl fmt::Debug for ProofType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProofType::Deadend => f.write_str("Deadend"),
+            ProofType::Short {
+                prefix,
+                left,
+                right,
+            } => f
+                .debug_struct("Short")
+                .field("prefix", prefix)
+                .field("left", &Hex(left))
+                .field("right", &Hex(right))
+                .finish(),
+            ProofType::Collision { key, hash } => f
+                .debug_struct("Collision")
+                .field("key", key)
+                .field("hash", &Hex(hash))
+                .finish(),
+            ProofType::Exists { value } => f
+                .debug_struct("Exists")
+                .field("value", &Hex(value))
+                .finish(),
+        }
+    }
+}
+
+impl ProofType {
+    fn tag(&self) -> u16 {
+        match self {
+            ProofType::Deadend => 0,
+            ProofType::Short { .. } => 1,
+            ProofType::Collision { .. } => 2,
+            ProofType::Exists { .. } => 3,
+        }
+    }
+}
+
+/// The structure of a proof, see [`Proof`] for the encoding.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct DecodedProof {
+    pub(crate) depth: u16,
+    pub(crate) nodes: Vec<ProofNode>,
+    pub(crate) ty: ProofType,
+}
+
+impl DecodedProof {
+    pub(crate) fn decode(raw: &[u8]) -> Result<DecodedProof, DecodeError> {
+        let mut r = Reader::new(raw);
+        let proof = DecodedProof::decode_with(&mut r, |r| r.array())?;
+        r.finish()?;
+        Ok(proof)
+    }
+
+    /// Decodes a proof, reading every node hash with `read_hash`.
+    ///
+    /// This allows reusing the layout in encodings that store node hashes elsewhere.
+    pub(crate) fn decode_with(
+        r: &mut Reader,
+        mut read_hash: impl FnMut(&mut Reader) -> Result<[u8; 32], DecodeError>,
+    ) -> Result<DecodedProof, DecodeError> {
+        let field = r.u16()?;
+        let depth = field & 0x3fff;
+        if depth as usize > KEY_BITS {
+            return Err(DecodeError::InvalidDepth(depth));
+        }
+        let count = r.u16()? as usize;
+        if count > KEY_BITS {
+            return Err(DecodeError::TooManyNodes(count));
+        }
+        let bitmap = r.bytes(count.div_ceil(8))?;
+        let mut nodes = Vec::with_capacity(count);
+        for i in 0..count {
+            let prefix = if bitmap[i / 8] & (0x80 >> (i % 8)) != 0 {
+                Some(Bits::decode(r)?)
+            } else {
+                None
+            };
+            let hash = read_hash(r)?;
+            nodes.push(ProofNode { prefix, hash });
+        }
+        let ty = match field >> 14 {
+            0 => ProofType::Deadend,
+            1 => ProofType::Short {
+                prefix: Bits::decode(r)?,
+                left: r.array()?,
+                right: r.array()?,
+            },
+            2 => ProofType::Collision {
+                key: Key::new(r.array()?),
+                hash: r.array()?,
+            },
+            _ => {
+                let size = r.u16()? as usize;
+                if size > MAX_VALUE_SIZE {
+                    return Err(DecodeError::ValueTooLarge(size));
+                }
+                ProofType::Exists {
+                    value: r.bytes(size)?.to_vec(),
+                }
+            }
+        };
+        Ok(DecodedProof { depth, nodes, ty })
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.encoded_len());
+        self.encode_with(&mut out, |out, hash| out.extend_from_slice(hash));
+        out
+    }
+
+    /// Encodes the proof, writing every node hash with `write_hash`.
+    pub(crate) fn encode_with(
+        &self,
+        out: &mut Vec<u8>,
+        mut write_hash: impl FnMut(&mut Vec<u8>, &[u8; 32]),
+    ) {
+        let field = self.ty.tag() << 14 | self.depth;
+        out.extend_from_slice(&field.to_le_bytes());
+        out.extend_from_slice(&(self.nodes.len() as u16).to_le_bytes());
+        let bitmap_start = out.len();
+        out.resize(bitmap_start + self.nodes.len().div_ceil(8), 0);
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node.prefix.is_some() {
+                out[bitmap_start + i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+        for node in &self.nodes {
+            if let Some(prefix) = &node.prefix {
+                prefix.encode(out);
+            }
+            write_hash(out, &node.hash);
+        }
+        match &self.ty {
+            ProofType::Deadend => {}
+            ProofType::Short {
+                prefix,
+                left,
+                right,
+            } => {
+                prefix.encode(out);
+                out.extend_from_slice(left);
+                out.extend_from_slice(right);
+            }
+            ProofType::Collision { key, hash } => {
+                out.extend_from_slice(key.as_bytes());
+                out.extend_from_slice(hash);
+            }
+            ProofType::Exists { value } => {
+                out.extend_from_slice(&(value.len() as u16).to_le_bytes());
+                out.extend_from_slice(value);
+            }
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        let nodes: usize = self
+            .nodes
+            .iter()
+            .map(|node| node.prefix.as_ref().map_or(0, Bits::encoded_len) + 32)
+            .sum();
+        let tail = match &self.ty {
+            ProofType::Deadend => 0,
+            ProofType::Short { prefix, .. } => prefix.encoded_len() + 64,
+            ProofType::Collision { .. } => 64,
+            ProofType::Exists { value } => 2 + value.len(),
+        };
+        4 + self.nodes.len().div_ceil(8) + nodes + tail
+    }
+}
+
+#[cfg(feature = "verify")]
+impl DecodedProof {
+    /// Verifies the proof for `key` against `root`, mirroring `urkel_proof_verify`.
+    ///
+    /// The hash of the node the path ends at is recomputed from the proof, and then hashed
+    /// together with the sibling nodes bottom-up until the root is reached.
+    fn verify(&self, key: &Key, root: Root) -> Result<Option<Vec<u8>>, VerifyError> {
+        let mut depth = self.depth as usize;
+        let mut next = match &self.ty {
+            ProofType::Deadend => [0; 32],
+            ProofType::Short {
+                prefix,
+                left,
+                right,
+            } => {
+                if prefix.has(key, depth) {
+                    return Err(VerifyError::SamePath);
+                }
+                hash_internal(prefix, left, right)
+            }
+            ProofType::Collision { key: other, hash } => {
+                if other == key {
+                    return Err(VerifyError::SameKey);
+                }
+                hash_leaf(other, hash)
+            }
+            ProofType::Exists { value } => hash_leaf(key, &hash_value(value)),
+        };
+
+        let empty = Bits {
+            size: 0,
+            data: Vec::new(),
+        };
+        for node in self.nodes.iter().rev() {
+            let prefix = node.prefix.as_ref().unwrap_or(&empty);
+            if depth < prefix.size + 1 {
+                return Err(VerifyError::NegativeDepth);
+            }
+            depth -= 1;
+            next = if key_bit(key, depth) {
+                hash_internal(prefix, &node.hash, &next)
+            } else {
+                hash_internal(prefix, &next, &node.hash)
+            };
+            depth -= prefix.size;
+            if !prefix.has(key, depth) {
+                return Err(VerifyError::PathMismatch);
+            }
+        }
+
+        if depth != 0 {
+            return Err(VerifyError::TooDeep);
+        }
+        if &next != root.as_bytes() {
+            return Err(VerifyError::HashMismatch);
+        }
+        Ok(match &self.ty {
+            ProofType::Exists { value } => Some(value.clone()),
+            _ => None,
+        })
+    }
+}
+
+#[cfg(feature = "verify")]
+impl Bits {
+    /// Checks whether these bits match the bits of `key` starting at `depth`.
+    fn has(&self, key: &Key, depth: usize) -> bool {
+        depth + self.size <= KEY_BITS
+            && (0..self.size).all(|i| self.get(i) == key_bit(key, depth + i))
+    }
+}
+
+#[cfg(feature = "verify")]
+fn key_bit(key: &Key, index: usize) -> bool {
+    key.as_bytes()[index / 8] & (0x80 >> (index % 8)) != 0
+}
+
+#[cfg(feature = "verify")]
+fn blake2b(parts: &[&[u8]]) -> [u8; 32] {
+    let mut ctx = blake2_rfc::blake2b::Blake2b::new(32);
+    for part in parts {
+        ctx.update(part);
+    }
+    let mut out = [0; 32];
+    out.copy_from_slice(ctx.finalize().as_bytes());
+    out
+}
+
+#[cfg(feature = "verify")]
+fn hash_value(value: &[u8]) -> [u8; 32] {
+    blake2b(&[value])
+}
+
+#[cfg(feature = "verify")]
+fn hash_leaf(key: &Key, value_hash: &[u8; 32]) -> [u8; 32] {
+    blake2b(&[&[0x00], key.as_bytes(), value_hash])
+}
+
+#[cfg(feature = "verify")]
+fn hash_internal(prefix: &Bits, left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    if prefix.size == 0 {
+        blake2b(&[&[0x01], left, right])
+    } else {
+        let size = (prefix.size as u16).to_le_bytes();
+        blake2b(&[&[0x02], &size, &prefix.data, left, right])
+    }
+}
+
+/// A cursor over an encoded proof.
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.data.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn array(&mut self) -> Result<[u8; 32], DecodeError> {
+        let mut array = [0; 32];
+        array.copy_from_slice(self.bytes(32)?);
+        Ok(array)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, DecodeError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, DecodeError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Checks that all of the input was consumed.
+    pub(crate) fn finish(self) -> Result<(), DecodeError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(DecodeError::TrailingBytes(self.data.len()))
+        }
+    }
+}
+
+/// An error decoding a proof.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DecodeError {
+    UnexpectedEnd,
+    TrailingBytes(usize),
+    InvalidDepth(u16),
+    TooManyNodes(usize),
+    InvalidPrefixSize(usize),
+    ValueTooLarge(usize),
+    UnsupportedVersion(u8),
+    InvalidHashIndex(u32),
+    NonCanonical,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "the proof ended unexpectedly"),
+            DecodeError::TrailingBytes(len) => {
+                write!(f, "{} unexpected bytes after the end of the proof", len)
+            }
+            DecodeError::InvalidDepth(depth) => {
+                write!(f, "the proof depth {} exceeds the key size", depth)
+            }
+            DecodeError::TooManyNodes(count) => {
+                write!(f, "the proof has {} nodes, more than the key size", count)
+            }
+            DecodeError::InvalidPrefixSize(size) => write!(f, "invalid prefix size {}", size),
+            DecodeError::ValueTooLarge(size) => {
+                write!(f, "the value is {} bytes, more than supported", size)
+            }
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported encoding version {}", version)
+            }
+            DecodeError::InvalidHashIndex(index) => {
+                write!(f, "node hash index {} is out of range", index)
+            }
+            DecodeError::NonCanonical => write!(f, "the proof is not canonically encoded"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
@@ -0,0 +1,91 @@
+use crate::error::ParseHexError;
+use core::fmt;
+use core::str::FromStr;
+
+/// The root hash of a tree.
+///
+/// Displayed and parsed as 64 hexadecimal characters.
+///
+/// ```
+/// # use urkel::Root;
+/// let root: Root = "58f8fd75fe4ebe990b2e84e497932ae7c4e29c841035a6fa9b6879d44902d73a"
+///     .parse()
+///     .unwrap();
+/// assert_eq!(
+///     root.to_string(),
+///     "58f8fd75fe4ebe990b2e84e497932ae7c4e29c841035a6fa9b6879d44902d73a"
+/// );
+/// ```
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Root([u8; 32]);
+
+impl Root {
+    /// The root of an empty tree, which is all zeroes.
+    pub const EMPTY: Root = Root([0; 32]);
+
+    pub const fn new(bytes: [u8; 32]) -> Self {
+        Root(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn to_bytes(self) -> [u8; 32] {
+        self.0
+    }
+
+    #[cfg(feature = "native")]
+    pub(crate) fn as_ptr(&self) -> *const u8 {
+        self.0.as_ptr()
+    }
+
+    #[cfg(feature = "native")]
+    pub(crate) fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.0.as_mut_ptr()
+    }
+}
+
+impl From<[u8; 32]> for Root {
+    fn from(bytes: [u8; 32]) -> Self {
+        Root(bytes)
+    }
+}
+
+impl From<Root> for [u8; 32] {
+    fn from(root: Root) -> Self {
+        root.0
+    }
+}
+
+impl AsRef<[u8]> for Root {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl fmt::LowerHex for Root {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        crate::util::write_hex(f, &self.0)
+    }
+}
+
+impl fmt::Display for Root {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::LowerHex::fmt(self, f)
+    }
+}
+
+impl fmt::Debug for Root {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Root({:x})", self)
+    }
+}
+
+impl FromStr for Root {
+    type Err = ParseHexError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        crate::util::parse_hex_32(s).map(Root)
+    }
+}
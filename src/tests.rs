@@ -1,4 +1,7 @@
-use crate::{Database, Proof, VerifyError};
+use crate::{
+    blake2b_256, Database, DatabaseOptions, DecodeError, Key, LargeValueProof, MultiProof,
+    ParseHexError, Proof, ProofType, Root, VerifyError,
+};
 use assert_matches::assert_matches;
 use hex_literal::hex;
 use std::fs::File;
@@ -54,7 +57,9 @@ fn open_err_handle() -> Result<(), AnyErr> {
     let _ = File::create(&file_path)?;
 
     // a database cannot be opened at a file
-    let _ = Database::open(file_path).unwrap_err();
+    let err = Database::open(&file_path).unwrap_err();
+    assert_matches!(err, crate::Error::Io { op: "open", .. });
+    assert_eq!(err.errno(), None);
     Ok(())
 }
 
@@ -62,9 +67,8 @@ fn open_err_handle() -> Result<(), AnyErr> {
 fn destroy_existing() -> Result<(), AnyErr> {
     let TmpDatabase { prefix_dir, db } = TmpDatabase::new()?;
     {
-        let tx = db.new_tx()?;
-        let key = [1; 1024 * 1024];
-        tx.insert(&key, b"hello")?;
+        let mut tx = db.new_tx()?;
+        tx.insert(&Key::new([1; 32]), b"hello")?;
         tx.commit()?;
     }
     drop(db);
@@ -81,6 +85,180 @@ fn destroy_non_existent() -> Result<(), AnyErr> {
     Ok(())
 }
 
+#[test]
+fn options_create_if_missing() -> Result<(), AnyErr> {
+    let tmp_dir = tempdir()?;
+    let prefix = tmp_dir.path().join("db");
+
+    let mut options = DatabaseOptions::new();
+    options.create_if_missing(false);
+    assert_matches!(
+        options.open(&prefix),
+        Err(crate::Error::DoesNotExist { ref path }) if path == &prefix
+    );
+    // An empty directory doesn't hold a store either.
+    assert_matches!(
+        options.open(tmp_dir.path()),
+        Err(crate::Error::DoesNotExist { .. })
+    );
+
+    drop(DatabaseOptions::new().open(&prefix)?);
+    options.open(&prefix)?;
+
+    Ok(())
+}
+
+#[test]
+fn options_error_if_exists() -> Result<(), AnyErr> {
+    let tmp_dir = tempdir()?;
+    let prefix = tmp_dir.path().join("db");
+
+    let mut options = DatabaseOptions::new();
+    options.error_if_exists(true);
+    drop(options.open(&prefix)?);
+    assert_matches!(
+        options.open(&prefix),
+        Err(crate::Error::AlreadyExists { ref path }) if path == &prefix
+    );
+
+    Ok(())
+}
+
+#[test]
+fn options_expected_root() -> Result<(), AnyErr> {
+    let TmpDatabase { prefix_dir, db } = TmpDatabase::new()?;
+    let mut tx = db.new_tx()?;
+    tx.insert(&Key::new([1; 32]), b"hello")?;
+    let root = tx.commit()?.root;
+    drop(tx);
+    drop(db);
+
+    DatabaseOptions::new()
+        .expected_root(root)
+        .open(prefix_dir.path())?;
+    let err = DatabaseOptions::new()
+        .expected_root(Root::EMPTY)
+        .open(prefix_dir.path())
+        .unwrap_err();
+    assert_matches!(
+        err,
+        crate::Error::RootMismatch { expected, actual } if expected == Root::EMPTY && actual == root
+    );
+
+    // No store is created at a wrong prefix.
+    let wrong_dir = prefix_dir.path().join("wrong");
+    let err = DatabaseOptions::new()
+        .expected_root(root)
+        .open(&wrong_dir)
+        .unwrap_err();
+    assert_matches!(
+        err,
+        crate::Error::RootMismatch { expected, actual } if expected == root && actual == Root::EMPTY
+    );
+    assert!(!wrong_dir.exists());
+
+    Ok(())
+}
+
+#[test]
+fn read_only_refuses_writes() -> Result<(), AnyErr> {
+    let key = Key::new([1; 32]);
+    let TmpDatabase { prefix_dir, db } = TmpDatabase::new()?;
+    let mut tx = db.new_tx()?;
+    tx.insert(&key, b"hello")?;
+    let root = tx.commit()?.root;
+    drop(tx);
+    drop(db);
+
+    let mut db = Database::open_read_only(prefix_dir.path())?;
+    assert!(db.is_read_only());
+    assert_eq!(db.root(), root);
+    assert_eq!(db.snapshot(root)?.get(&key)?, Some(b"hello".to_vec()));
+
+    assert_matches!(db.insert(&key, b"world"), Err(crate::Error::ReadOnly));
+    assert_matches!(db.remove(&key), Err(crate::Error::ReadOnly));
+    assert_matches!(db.set_head(Root::EMPTY), Err(crate::Error::ReadOnly));
+    let mut batch = crate::WriteBatch::new();
+    batch.put(&key, b"world");
+    assert_matches!(db.write(&batch), Err(crate::Error::ReadOnly));
+
+    // Transactions work in memory, but can't be committed.
+    let mut tx = db.new_tx()?;
+    tx.insert(&key, b"world")?;
+    assert_matches!(tx.commit(), Err(crate::Error::ReadOnly));
+    drop(tx);
+    assert_eq!(db.root(), root);
+
+    let tmp_dir = tempdir()?;
+    assert_matches!(
+        Database::open_read_only(tmp_dir.path().join("db")),
+        Err(crate::Error::DoesNotExist { .. })
+    );
+
+    Ok(())
+}
+
+#[test]
+fn read_only_refresh() -> Result<(), AnyErr> {
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&key1, b"hello")?;
+    let root1 = tx.commit()?.root;
+
+    let mut reader = Database::open_read_only(tmp_db.prefix_dir.path())?;
+    assert_eq!(reader.root(), root1);
+
+    tx.insert(&key2, b"world")?;
+    let root2 = tx.commit()?.root;
+
+    assert_eq!(reader.root(), root1);
+    assert_eq!(reader.refresh()?, root2);
+    assert_eq!(reader.snapshot(root2)?.get(&key2)?, Some(b"world".to_vec()));
+
+    Ok(())
+}
+
+#[test]
+fn lock_prevents_second_writer() -> Result<(), AnyErr> {
+    let tmp_db = TmpDatabase::new()?;
+    let prefix = tmp_db.prefix_dir.path();
+
+    assert_matches!(
+        Database::open(prefix),
+        Err(crate::Error::Locked { holder_pid: Some(pid) }) if pid == std::process::id()
+    );
+    assert_matches!(Database::destroy(prefix), Err(crate::Error::Locked { .. }));
+    // Readers don't take the lock.
+    let reader = Database::open_read_only(prefix)?;
+    assert!(reader.is_read_only());
+
+    let TmpDatabase { prefix_dir, db } = tmp_db;
+    drop(db);
+    let db = Database::open(prefix_dir.path())?;
+    assert!(!db.is_read_only());
+    drop(db);
+    drop(reader);
+    Database::destroy(prefix_dir.path())?;
+
+    Ok(())
+}
+
+#[test]
+fn lock_file_is_not_a_store() -> Result<(), AnyErr> {
+    let tmp_dir = tempdir()?;
+    File::create(tmp_dir.path().join("LOCK"))?;
+    File::create(tmp_dir.path().join("COMMIT"))?;
+    assert_matches!(
+        DatabaseOptions::new()
+            .create_if_missing(false)
+            .open(tmp_dir.path()),
+        Err(crate::Error::DoesNotExist { .. })
+    );
+    Ok(())
+}
+
 #[test]
 fn mk_empty_tx() -> Result<(), AnyErr> {
     let tmp_db = TmpDatabase::new()?;
@@ -100,7 +278,7 @@ fn mk_two_empty_tx() -> Result<(), AnyErr> {
 fn mk_empty_tx_root() -> Result<(), AnyErr> {
     let tmp_db = TmpDatabase::new()?;
     let tx = tmp_db.db.new_tx()?;
-    assert_eq!(tx.root(), [0; 32]);
+    assert_eq!(tx.root(), Root::EMPTY);
     Ok(())
 }
 
@@ -108,29 +286,38 @@ fn mk_empty_tx_root() -> Result<(), AnyErr> {
 fn mk_tx_bogus_root() -> Result<(), AnyErr> {
     let tmp_db = TmpDatabase::new()?;
     assert!(matches!(
-        tmp_db.db.new_tx_at([3; 32]),
+        tmp_db.db.new_tx_at(Root::new([3; 32])),
         Err(crate::Error::NotFound)
     ));
     Ok(())
 }
 
 #[test]
-fn tx_insert_key_too_large() -> Result<(), AnyErr> {
-    let tmp_db = TmpDatabase::new()?;
-    let tx = tmp_db.db.new_tx()?;
-    let key = [1; 1024 * 1024];
-    tx.insert(&key, b"hello")?;
-    tx.commit()?;
-    assert!(tx.has(&key)?);
-    Ok(())
+fn key_from_slice() {
+    assert_matches!(
+        Key::from_slice(&vec![1; 1024 * 1024]),
+        Err(crate::Error::InvalidKeyLength(1048576))
+    );
+    assert_matches!(
+        Key::from_slice(&[1; 31]),
+        Err(crate::Error::InvalidKeyLength(31))
+    );
+    assert_eq!(Key::from_slice(&[1; 32]).unwrap(), Key::new([1; 32]));
+}
+
+#[test]
+fn key_hash_of() {
+    let id = vec![1; 1024 * 1024];
+    assert_eq!(Key::hash_of(&id).to_bytes(), crate::blake2b_256(&id));
+    assert_ne!(Key::hash_of(b"alice"), Key::hash_of(b"bob"));
 }
 
 #[test]
 fn tx_insert_value_too_large() -> Result<(), AnyErr> {
     let tmp_db = TmpDatabase::new()?;
-    let tx = tmp_db.db.new_tx()?;
+    let mut tx = tmp_db.db.new_tx()?;
     assert_matches!(
-        tx.insert(&[1; 31], &[0u8; 1025]),
+        tx.insert(&Key::new([1; 32]), &[0u8; 1025]),
         Err(crate::Error::ValueTooLarge)
     );
     Ok(())
@@ -139,12 +326,14 @@ fn tx_insert_value_too_large() -> Result<(), AnyErr> {
 #[test]
 fn tx_insert() -> Result<(), AnyErr> {
     let tmp_db = TmpDatabase::new()?;
-    let tx = tmp_db.db.new_tx()?;
-    tx.insert(&[1; 32], b"hello")?;
-    assert!(tx.has(&[1; 32])?);
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&Key::new([1; 32]), b"hello")?;
+    assert!(tx.has(&Key::new([1; 32]))?);
     assert_eq!(
         tx.root(),
-        hex!("58f8fd75fe4ebe990b2e84e497932ae7c4e29c841035a6fa9b6879d44902d73a")
+        Root::new(hex!(
+            "58f8fd75fe4ebe990b2e84e497932ae7c4e29c841035a6fa9b6879d44902d73a"
+        ))
     );
     Ok(())
 }
@@ -153,14 +342,14 @@ fn tx_insert() -> Result<(), AnyErr> {
 fn tx_insert_has_no_effect_without_commit() -> Result<(), AnyErr> {
     let tmp_db = TmpDatabase::new()?;
     {
-        let tx = tmp_db.db.new_tx()?;
-        tx.insert(&[1; 32], b"hello")?;
+        let mut tx = tmp_db.db.new_tx()?;
+        tx.insert(&Key::new([1; 32]), b"hello")?;
         drop(tx);
     }
 
     {
         let tx = tmp_db.db.new_tx()?;
-        assert!(!tx.has(&[1; 32])?);
+        assert!(!tx.has(&Key::new([1; 32]))?);
     }
 
     Ok(())
@@ -170,14 +359,14 @@ fn tx_insert_has_no_effect_without_commit() -> Result<(), AnyErr> {
 fn tx_is_isolated() -> Result<(), AnyErr> {
     let tmp_db = TmpDatabase::new()?;
 
-    let key1 = [1; 32];
-    let key2 = [2; 32];
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
 
-    let tx1 = tmp_db.db.new_tx()?;
+    let mut tx1 = tmp_db.db.new_tx()?;
     tx1.insert(&key1, b"hello")?;
     assert!(tx1.has(&key1)?);
 
-    let tx2 = tmp_db.db.new_tx()?;
+    let mut tx2 = tmp_db.db.new_tx()?;
     tx2.insert(&key2, b"hello")?;
     assert!(!tx2.has(&key1)?);
     assert!(tx2.has(&key2)?);
@@ -192,18 +381,20 @@ fn tx_is_isolated() -> Result<(), AnyErr> {
 fn tx_insert_reopen() -> Result<(), AnyErr> {
     let mut tmp_db = TmpDatabase::new()?;
     {
-        let tx = tmp_db.db.new_tx()?;
-        tx.insert(&[1; 32], b"hello")?;
+        let mut tx = tmp_db.db.new_tx()?;
+        tx.insert(&Key::new([1; 32]), b"hello")?;
         tx.commit()?;
     }
 
     tmp_db = tmp_db.reopen()?;
     {
         let tx = tmp_db.db.new_tx()?;
-        assert!(tx.has(&[1; 32])?);
+        assert!(tx.has(&Key::new([1; 32]))?);
         assert_eq!(
             tx.root(),
-            hex!("58f8fd75fe4ebe990b2e84e497932ae7c4e29c841035a6fa9b6879d44902d73a")
+            Root::new(hex!(
+                "58f8fd75fe4ebe990b2e84e497932ae7c4e29c841035a6fa9b6879d44902d73a"
+            ))
         );
     }
 
@@ -212,28 +403,28 @@ fn tx_insert_reopen() -> Result<(), AnyErr> {
 
 #[test]
 fn tx_iter() -> Result<(), AnyErr> {
-    let key1 = [1; 32];
-    let key2 = [2; 32];
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
 
     let tmp_db = TmpDatabase::new()?;
-    let tx = tmp_db.db.new_tx()?;
+    let mut tx = tmp_db.db.new_tx()?;
     tx.insert(&key1, b"hello")?;
     tx.insert(&key2, b"world")?;
 
-    let iter = tx.iter()?;
-    assert_eq!(iter.next()?, Some((key1.clone(), b"hello".to_vec())));
-    assert_eq!(iter.next()?, Some((key2.clone(), b"world".to_vec())));
-    assert_eq!(iter.next()?, None);
+    let mut iter = tx.iter()?;
+    assert_eq!(iter.next().transpose()?, Some((key1, b"hello".to_vec())));
+    assert_eq!(iter.next().transpose()?, Some((key2, b"world".to_vec())));
+    assert_eq!(iter.next().transpose()?, None);
 
     Ok(())
 }
 
 #[test]
 fn proof_of_existence() -> Result<(), AnyErr> {
-    let key = [1; 32];
+    let key = Key::new([1; 32]);
 
     let tmp_db = TmpDatabase::new()?;
-    let tx = tmp_db.db.new_tx()?;
+    let mut tx = tmp_db.db.new_tx()?;
     tx.insert(&key, b"hello")?;
     let proof = tx.prove(&key)?;
     let root = tx.root();
@@ -246,7 +437,7 @@ fn proof_of_existence() -> Result<(), AnyErr> {
 
 #[test]
 fn proof_of_non_existence() -> Result<(), AnyErr> {
-    let key = [2; 32];
+    let key = Key::new([2; 32]);
 
     let tmp_db = TmpDatabase::new()?;
     let tx = tmp_db.db.new_tx()?;
@@ -262,12 +453,12 @@ fn proof_of_non_existence() -> Result<(), AnyErr> {
 
 #[test]
 fn bogus_proofs() -> Result<(), AnyErr> {
-    let key1 = [1; 32];
-    let key2 = [2; 32];
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
 
     let tmp_db = TmpDatabase::new()?;
 
-    let tx = tmp_db.db.new_tx()?;
+    let mut tx = tmp_db.db.new_tx()?;
     tx.insert(&key1, b"hello")?;
     let proof = tx.prove(&key1)?;
     let root = tx.root();
@@ -277,29 +468,1665 @@ fn bogus_proofs() -> Result<(), AnyErr> {
     let proof = Proof::new_unchecked(b"bogus".to_vec());
     assert_matches!(proof.verify(&key1, root), Err(VerifyError::InvalidProof));
 
-    let bogus_root = [3; 32];
+    let bogus_root = Root::new([3; 32]);
     assert_matches!(tmp_db.db.prove(&key1, bogus_root), Err(_));
 
     Ok(())
 }
 
 #[test]
-fn fuzz_1() -> Result<(), AnyErr> {
+fn multiproof_roundtrip() -> Result<(), AnyErr> {
     let tmp_db = TmpDatabase::new()?;
-    let tx = tmp_db.db.new_tx()?;
-    tx.insert(
-        &hex!("0000000000000000000000000000000000000000000000000000000000000001"),
-        &[],
+    let mut tx = tmp_db.db.new_tx()?;
+    let mut keys = Vec::new();
+    for i in 0..64u32 {
+        let key = Key::hash_of(&i.to_le_bytes());
+        if i % 2 == 0 {
+            tx.insert(&key, &i.to_le_bytes())?;
+        }
+        keys.push(key);
+    }
+    let root = tx.commit()?.root;
+
+    let multiproof = tmp_db.db.prove_many(&keys, root)?;
+    assert_eq!(multiproof.len(), keys.len());
+
+    // Splitting the multiproof gives back exactly the single-key proofs.
+    let singles = keys
+        .iter()
+        .map(|key| tmp_db.db.prove(key, root))
+        .collect::<Result<Vec<_>, _>>()?;
+    for (proof, single) in multiproof.to_proofs().iter().zip(&singles) {
+        assert_eq!(proof.encode(), single.encode());
+    }
+
+    let encoded = multiproof.encode();
+    let total: usize = singles.iter().map(|proof| proof.encode().len()).sum();
+    assert!(encoded.len() < total);
+    let decoded = MultiProof::decode(&encoded)?;
+    assert_eq!(decoded, multiproof);
+
+    let values = decoded.verify(&keys, root)?;
+    for (i, value) in values.into_iter().enumerate() {
+        let expected = if i % 2 == 0 {
+            Some((i as u32).to_le_bytes().to_vec())
+        } else {
+            None
+        };
+        assert_eq!(value, expected);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn large_value_roundtrip() -> Result<(), AnyErr> {
+    let key = Key::hash_of(b"contract");
+    let value = (0..10 * 1024 + 7).map(|i| i as u8).collect::<Vec<_>>();
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    assert_eq!(tx.get_large(&key)?, None);
+    tx.put_large(&key, &value)?;
+    assert_eq!(tx.get_large(&key)?, Some(value.clone()));
+    // The manifest and 11 chunks.
+    assert_eq!(tx.iter()?.count(), 12);
+    let root = tx.commit()?.root;
+
+    let proof = tx.prove_large(&key)?;
+    assert_eq!(proof.chunks().len(), 11);
+    let decoded = LargeValueProof::decode(&proof.encode())?;
+    assert_eq!(decoded, proof);
+    assert_eq!(decoded.verify(&key, root)?, Some(value.clone()));
+    assert!(decoded.verify(&Key::hash_of(b"other"), root).is_err());
+
+    let absent = tx.prove_large(&Key::hash_of(b"other"))?;
+    assert!(absent.chunks().is_empty());
+    assert_eq!(absent.verify(&Key::hash_of(b"other"), root)?, None);
+
+    // Shrinking the value drops the chunks that are no longer used.
+    tx.put_large(&key, &value[..100])?;
+    assert_eq!(tx.iter()?.count(), 2);
+    assert_eq!(tx.get_large(&key)?, Some(value[..100].to_vec()));
+
+    tx.remove_large(&key)?;
+    assert_eq!(tx.root(), Root::EMPTY);
+    assert_matches!(tx.remove_large(&key), Err(crate::Error::NotFound));
+
+    Ok(())
+}
+
+#[test]
+fn large_value_bogus() -> Result<(), AnyErr> {
+    let key = Key::hash_of(b"contract");
+    let value = vec![7; 3000];
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&key, b"small")?;
+    assert_matches!(tx.get_large(&key), Err(crate::Error::InvalidLargeValue(k)) if k == key);
+    assert_matches!(
+        tx.prove_large(&key),
+        Err(crate::Error::InvalidLargeValue(_))
+    );
+
+    // A look-alike manifest with more chunks than can be addressed is overwritten as is.
+    let look_alike = [&b"ulv\0"[..], &u64::MAX.to_le_bytes(), &[0; 32]].concat();
+    tx.insert(&key, &look_alike)?;
+    assert_matches!(tx.get_large(&key), Err(crate::Error::InvalidLargeValue(_)));
+
+    tx.put_large(&key, &value)?;
+    let root = tx.root();
+    let proof = tx.prove_large(&key)?;
+
+    // A proof of a different value, or of no chunks at all, doesn't verify.
+    tx.put_large(&key, &value[..2000])?;
+    let other = tx.prove_large(&key)?;
+    let mixed = LargeValueProof::decode(
+        &[
+            &(proof.manifest().encode().len() as u32).to_le_bytes()[..],
+            &proof.manifest().encode(),
+            &other.chunks().encode(),
+        ]
+        .concat(),
     )?;
-    tx.commit()?;
-    tx.commit()?;
+    assert_eq!(mixed.verify(&key, root), Err(VerifyError::InvalidProof));
+
+    // A missing chunk is reported.
+    tx.put_large(&key, &value)?;
+    let mut batch = crate::WriteBatch::new();
+    for leaf in tx.iter()? {
+        let (leaf_key, _) = leaf?;
+        if leaf_key != key {
+            batch.delete(&leaf_key);
+            break;
+        }
+    }
+    tx.apply(&batch)?;
+    assert_matches!(tx.get_large(&key), Err(crate::Error::InvalidLargeValue(_)));
+
+    Ok(())
+}
+
+#[test]
+fn blob_roundtrip() -> Result<(), AnyErr> {
+    let key = Key::hash_of(b"payload");
+    let blob = (0..100 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let hash = blake2b_256(&blob);
+
+    let mut tmp_db = TmpDatabase::new()?;
+    let root = {
+        let mut tx = tmp_db.db.new_tx()?;
+        assert_eq!(tx.get_blob(&key)?, None);
+        tx.put_blob(&key, &blob)?;
+        assert_eq!(tx.get(&key)?, Some(hash.to_vec()));
+        assert_eq!(tx.get_blob(&key)?, Some(blob.clone()));
+        tx.commit()?.root
+    };
+    assert!(tmp_db.db.blobs().contains(&hash));
+
+    // The proof of the key commits to the hash of the blob.
+    let proof = tmp_db.db.prove(&key, root)?;
+    assert_eq!(proof.verify(&key, root)?, Some(hash.to_vec()));
+
+    // A blob of an uncommitted transaction is swept.
+    {
+        let mut tx = tmp_db.db.new_tx()?;
+        tx.put_blob(&Key::hash_of(b"dropped"), b"never committed")?;
+    }
+    assert!(tmp_db.db.blobs().contains(&blake2b_256(b"never committed")));
+    // The temporary file of an interrupted write is swept without counting as a blob.
+    let blobs_dir = tmp_db.prefix_dir.path().join("blobs");
+    assert_eq!(std::fs::read_dir(&blobs_dir)?.count(), 2);
+    let stray = blobs_dir.join(format!("{}.1.0.tmp", crate::util::Hex(&hash)));
+    std::fs::write(&stray, b"interrupted")?;
+    // The blobs of the current root are kept even if it's not retained explicitly.
+    assert_eq!(tmp_db.db.sweep_blobs(&[])?, 1);
+    assert!(!tmp_db.db.blobs().contains(&blake2b_256(b"never committed")));
+    assert!(tmp_db.db.blobs().contains(&hash));
+    assert!(!stray.exists());
+
+    // A blob is kept as long as a retained root refers to it.
+    let root2 = {
+        let mut tx = tmp_db.db.new_tx()?;
+        tx.remove(&key)?;
+        tx.commit()?.root
+    };
+    assert_eq!(tmp_db.db.sweep_blobs(&[root, root2])?, 0);
+    assert_eq!(tmp_db.db.new_tx_at(root)?.get_blob(&key)?, Some(blob));
+    assert_eq!(tmp_db.db.sweep_blobs(&[root2])?, 1);
+    assert!(!tmp_db.db.blobs().contains(&hash));
+
+    let TmpDatabase { prefix_dir, db } = tmp_db;
+    drop(db);
+    Database::destroy(prefix_dir.path())?;
+
+    Ok(())
+}
+
+#[test]
+fn blob_bogus() -> Result<(), AnyErr> {
+    let key = Key::hash_of(b"payload");
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+
+    tx.insert(&key, b"not a hash")?;
+    assert_matches!(tx.get_blob(&key), Err(crate::Error::InvalidBlobReference(k)) if k == key);
 
+    tx.insert(&key, &blake2b_256(b"unknown"))?;
+    assert_matches!(tx.get_blob(&key), Err(crate::Error::MissingBlob(k)) if k == key);
+
+    tx.put_blob(&key, b"blob")?;
+    let path = tmp_db
+        .prefix_dir
+        .path()
+        .join("blobs")
+        .join(crate::util::Hex(&blake2b_256(b"blob")).to_string());
+    std::fs::write(&path, b"tampered")?;
     assert_matches!(
-        tx.remove(&hex!(
-            "0000000000000000000000000000000000000000000000000000000000000000"
-        )),
+        tx.get_blob(&key),
+        Err(crate::Error::CorruptBlob { path: ref corrupt }) if corrupt == &path
+    );
+
+    let reader = Database::open_read_only(tmp_db.prefix_dir.path())?;
+    assert_matches!(
+        reader.new_tx()?.put_blob(&key, b"blob"),
+        Err(crate::Error::ReadOnly)
+    );
+
+    Ok(())
+}
+
+/// Commits `count` roots, each rewriting the same 100 keys.
+fn commit_rounds(db: &Database, count: u32) -> Result<Vec<Root>, AnyErr> {
+    let mut roots = Vec::new();
+    let mut tx = db.new_tx()?;
+    for round in 0..count {
+        for i in 0..100u32 {
+            tx.insert(&Key::hash_of(&i.to_le_bytes()), &[round as u8; 100])?;
+        }
+        roots.push(tx.commit()?.root);
+    }
+    Ok(roots)
+}
+
+#[test]
+fn compact_drops_old_roots() -> Result<(), AnyErr> {
+    let mut tmp_db = TmpDatabase::new()?;
+    let roots = commit_rounds(&tmp_db.db, 20)?;
+    let head = tmp_db.db.root();
+
+    let report = tmp_db.db.compact(&[roots[10], head, roots[10]])?;
+    assert_eq!(report.retained, vec![roots[10], head]);
+    assert!(report.bytes_reclaimed() > 0);
+    assert_eq!(tmp_db.db.root(), head);
+
+    let key = Key::hash_of(&0u32.to_le_bytes());
+    assert_eq!(
+        tmp_db.db.snapshot(roots[10])?.get(&key)?,
+        Some(vec![10; 100])
+    );
+    assert_matches!(tmp_db.db.snapshot(roots[5]), Err(crate::Error::NotFound));
+    assert_matches!(tmp_db.db.compact(&[roots[5]]), Err(crate::Error::NotFound));
+
+    tmp_db = tmp_db.reopen()?;
+    assert_eq!(tmp_db.db.root(), head);
+    assert_eq!(tmp_db.db.snapshot(head)?.get(&key)?, Some(vec![19; 100]));
+    assert!(!tmp_db.prefix_dir.path().join("compact").exists());
+
+    Ok(())
+}
+
+#[test]
+fn compact_interrupted() -> Result<(), AnyErr> {
+    let tmp_db = TmpDatabase::new()?;
+    let roots = commit_rounds(&tmp_db.db, 5)?;
+    let compact_dir = tmp_db.prefix_dir.path().join("compact");
+
+    // An incomplete compaction is discarded.
+    std::fs::create_dir(&compact_dir)?;
+    File::create(compact_dir.join("0000000001"))?;
+    let tmp_db = tmp_db.reopen()?;
+    assert!(!compact_dir.exists());
+    assert_eq!(tmp_db.db.root(), roots[4]);
+    tmp_db.db.snapshot(roots[0])?;
+
+    // A complete one is finished, as if the process was killed before the switch.
+    crate::compact::rebuild(&tmp_db.db, tmp_db.prefix_dir.path(), &[roots[2], roots[4]])?;
+    let tmp_db = tmp_db.reopen()?;
+    assert!(!compact_dir.exists());
+    assert_eq!(tmp_db.db.root(), roots[4]);
+    tmp_db.db.snapshot(roots[2])?;
+    assert_matches!(tmp_db.db.snapshot(roots[0]), Err(crate::Error::NotFound));
+
+    Ok(())
+}
+
+#[test]
+fn retention_policy() -> Result<(), AnyErr> {
+    let prefix_dir = tempdir()?;
+    let mut db = DatabaseOptions::new()
+        .retention_limit(2)
+        .open(prefix_dir.path())?;
+    let mut roots = Vec::new();
+    for i in 0..3u8 {
+        db.insert(&Key::new([i; 32]), b"value")?;
+        roots.push(db.root());
+    }
+    // With the empty root, the three commits make four roots, so the last one compacted.
+    assert_matches!(db.snapshot(roots[0]), Err(crate::Error::NotFound));
+    db.snapshot(roots[1])?;
+    assert_eq!(db.apply_retention()?, None);
+
+    // The compaction due after transaction commits waits for the next direct write.
+    let tx_roots = commit_rounds(&db, 2)?;
+    db.snapshot(roots[1])?;
+    db.insert(&Key::new([3; 32]), b"value")?;
+    assert_matches!(db.snapshot(roots[2]), Err(crate::Error::NotFound));
+    assert_matches!(db.snapshot(tx_roots[0]), Err(crate::Error::NotFound));
+    db.snapshot(tx_roots[1])?;
+    db.snapshot(db.root())?;
+
+    // Or for the policy to be applied explicitly.
+    let tx_roots = commit_rounds(&db, 2)?;
+    let report = db
+        .apply_retention()?
+        .expect("the store is due for compaction");
+    assert_eq!(report.retained, tx_roots);
+
+    Ok(())
+}
+
+#[test]
+fn export_import_roundtrip() -> Result<(), AnyErr> {
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    for i in 0..5000u32 {
+        tx.insert(&Key::hash_of(&i.to_le_bytes()), &i.to_le_bytes())?;
+    }
+    let old_root = tx.commit()?.root;
+    tx.remove(&Key::hash_of(&0u32.to_le_bytes()))?;
+    let root = tx.commit()?.root;
+
+    let mut snapshot = Vec::new();
+    assert_eq!(tmp_db.db.export(old_root, &mut snapshot)?, 5000);
+
+    let import_dir = tempdir()?;
+    let imported = Database::import(import_dir.path().join("db"), snapshot.as_slice())?;
+    assert_eq!(imported.root(), old_root);
+    assert!(imported
+        .iter(old_root)?
+        .map(Result::unwrap)
+        .eq(tmp_db.db.iter(old_root)?.map(Result::unwrap)));
+
+    drop(imported);
+    assert_matches!(
+        Database::import(import_dir.path().join("db"), snapshot.as_slice()),
+        Err(crate::Error::AlreadyExists { .. })
+    );
+    // The existing store is left alone.
+    assert_eq!(
+        Database::open(import_dir.path().join("db"))?.root(),
+        old_root
+    );
+
+    let mut snapshot = Vec::new();
+    assert_eq!(tmp_db.db.export(root, &mut snapshot)?, 4999);
+    let imported = Database::import(import_dir.path().join("db2"), snapshot.as_slice())?;
+    assert_eq!(imported.root(), root);
+
+    Ok(())
+}
+
+#[test]
+fn import_bogus() -> Result<(), AnyErr> {
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&Key::new([1; 32]), b"hello")?;
+    let root = tx.commit()?.root;
+    let mut snapshot = Vec::new();
+    tmp_db.db.export(root, &mut snapshot)?;
+    // header 41 bytes, one leaf of 35 + 5 bytes, trailer 9 + 32 bytes
+    assert_eq!(snapshot.len(), 41 + 40 + 41);
+
+    let import_dir = tempdir()?;
+    let prefix = import_dir.path().join("db");
+    let import = |snapshot: &[u8]| Database::import(&prefix, snapshot).map(drop);
+
+    assert_matches!(
+        import(&snapshot[..100]),
+        Err(crate::Error::MalformedSnapshot("the snapshot is truncated"))
+    );
+    // A failed import leaves nothing behind.
+    assert!(!prefix.exists());
+
+    let mut tampered = snapshot.clone();
+    tampered[80] ^= 1;
+    assert_matches!(
+        import(&tampered),
+        Err(crate::Error::MalformedSnapshot(
+            "the checksum doesn't match"
+        ))
+    );
+
+    let mut tampered = snapshot.clone();
+    tampered[8] = 1;
+    assert_matches!(
+        import(&tampered),
+        Err(crate::Error::MalformedSnapshot("unsupported version"))
+    );
+
+    // A snapshot claiming another root, with a valid checksum.
+    let mut tampered = snapshot.clone();
+    tampered[9..41].copy_from_slice(&[7; 32]);
+    let mut checksum = [0; 32];
+    for record in [&tampered[..41], &tampered[41..81], &tampered[81..90]] {
+        checksum = blake2b_256(&[&checksum[..], record].concat());
+    }
+    tampered[90..].copy_from_slice(&checksum);
+    assert_matches!(
+        import(&tampered),
+        Err(crate::Error::RootMismatch { expected, actual })
+            if expected == Root::new([7; 32]) && actual == root
+    );
+
+    import(&snapshot)?;
+    Ok(())
+}
+
+#[test]
+fn delta_chain() -> Result<(), AnyErr> {
+    let source = TmpDatabase::new()?;
+    let mut tx = source.db.new_tx()?;
+    for i in 0..1000u32 {
+        tx.insert(&Key::hash_of(&i.to_le_bytes()), &i.to_le_bytes())?;
+    }
+    let mut roots = vec![tx.commit()?.root];
+    for round in 1..4u32 {
+        for i in 0..50u32 {
+            let key = Key::hash_of(&(i * round).to_le_bytes());
+            if i % 3 == 0 && tx.has(&key)? {
+                tx.remove(&key)?;
+            } else {
+                tx.insert(&key, &[round as u8; 10])?;
+            }
+            tx.insert(&Key::hash_of(&(5000 + i * round).to_le_bytes()), b"new")?;
+        }
+        roots.push(tx.commit()?.root);
+    }
+
+    let mut snapshot = Vec::new();
+    source.db.export(roots[0], &mut snapshot)?;
+    let import_dir = tempdir()?;
+    let replica = Database::import(import_dir.path(), snapshot.as_slice())?;
+
+    for pair in roots.windows(2) {
+        let mut delta = Vec::new();
+        let changes = source.db.export_delta(pair[0], pair[1], &mut delta)?;
+        assert_eq!(changes as usize, source.db.diff(pair[0], pair[1])?.count());
+        assert_eq!(replica.apply_delta(delta.as_slice())?, pair[1]);
+        assert_eq!(replica.root(), pair[1]);
+    }
+
+    // The source root must be known locally.
+    let mut delta = Vec::new();
+    source.db.export_delta(roots[3], roots[0], &mut delta)?;
+    let other = TmpDatabase::new()?;
+    assert_matches!(
+        other.db.apply_delta(delta.as_slice()),
         Err(crate::Error::NotFound)
     );
+    // A delta can also go back to an earlier root.
+    assert_eq!(replica.apply_delta(delta.as_slice())?, roots[0]);
+
+    Ok(())
+}
+
+#[test]
+fn delta_bogus() -> Result<(), AnyErr> {
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&Key::new([1; 32]), b"hello")?;
+    let root1 = tx.commit()?.root;
+    tx.insert(&Key::new([2; 32]), b"world")?;
+    let root2 = tx.commit()?.root;
+
+    let mut delta = Vec::new();
+    tmp_db.db.export_delta(root1, root2, &mut delta)?;
+    // header 73 bytes, one leaf of 35 + 5 bytes, trailer 9 + 32 bytes
+    assert_eq!(delta.len(), 73 + 40 + 41);
+
+    let mut tampered = delta.clone();
+    tampered[112] ^= 1;
+    assert_matches!(
+        tmp_db.db.apply_delta(tampered.as_slice()),
+        Err(crate::Error::MalformedSnapshot(
+            "the checksum doesn't match"
+        ))
+    );
+    assert_matches!(
+        tmp_db.db.apply_delta(&delta[..120]),
+        Err(crate::Error::MalformedSnapshot("the snapshot is truncated"))
+    );
+    // A full snapshot isn't a delta.
+    let mut snapshot = Vec::new();
+    tmp_db.db.export(root2, &mut snapshot)?;
+    assert_matches!(
+        tmp_db.db.apply_delta(snapshot.as_slice()),
+        Err(crate::Error::MalformedSnapshot("unexpected magic"))
+    );
+    assert_eq!(tmp_db.db.root(), root2);
+
+    let reader = Database::open_read_only(tmp_db.prefix_dir.path())?;
+    assert_matches!(
+        reader.apply_delta(delta.as_slice()),
+        Err(crate::Error::ReadOnly)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn multiproof_bogus() -> Result<(), AnyErr> {
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&key1, b"hello")?;
+    tx.insert(&key2, b"world")?;
+    let multiproof = tx.prove_many(&[key1, key2])?;
+    let root = tx.root();
+
+    assert_matches!(
+        multiproof.verify(&[key2, key1], root),
+        Err(VerifyError::HashMismatch)
+    );
+    assert_matches!(
+        multiproof.verify(&[key1], root),
+        Err(VerifyError::InvalidProof)
+    );
+
+    let mut encoded = multiproof.encode();
+    assert_matches!(
+        MultiProof::decode(&encoded[..encoded.len() - 1]),
+        Err(DecodeError::UnexpectedEnd)
+    );
+    encoded.push(0);
+    assert_matches!(
+        MultiProof::decode(&encoded),
+        Err(DecodeError::TrailingBytes(1))
+    );
+    encoded[0] = 1;
+    assert_matches!(
+        MultiProof::decode(&encoded),
+        Err(DecodeError::UnsupportedVersion(1))
+    );
+
+    // A hash that no proof refers to is rejected.
+    let mut encoded = multiproof.encode();
+    let end = 5 + 32 * encoded[1] as usize;
+    encoded[1] += 1;
+    encoded.splice(end..end, [7; 32]);
+    assert_matches!(MultiProof::decode(&encoded), Err(DecodeError::NonCanonical));
+
+    Ok(())
+}
+
+#[test]
+fn proof_structure() -> Result<(), AnyErr> {
+    let key1 = Key::new([0x00; 32]);
+    let key2 = Key::new([0xff; 32]);
+    let key3 = Key::new([0x0f; 32]);
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    assert_matches!(tx.prove(&key1)?.proof_type(), Some(ProofType::Deadend));
+
+    tx.insert(&key1, b"hello")?;
+    let proof = tx.prove(&key3)?;
+    assert_eq!(proof.depth(), Some(0));
+    assert_eq!(
+        proof.proof_type(),
+        Some(&ProofType::Collision {
+            key: key1,
+            hash: blake2b_256(b"hello"),
+        })
+    );
+
+    tx.insert(&key2, b"world")?;
+    // The keys diverge at the first bit, so the root has both leaves as its children.
+    let proof = tx.prove(&key2)?;
+    assert_eq!(proof.depth(), Some(1));
+    assert_eq!(proof.value(), Some(&b"world"[..]));
+    let nodes = proof.nodes().unwrap();
+    assert_eq!(nodes.len(), 1);
+    assert_eq!(nodes[0].prefix, None);
+    let mut leaf = vec![0x00];
+    leaf.extend_from_slice(key1.as_bytes());
+    leaf.extend_from_slice(&blake2b_256(b"hello"));
+    assert_eq!(nodes[0].hash, blake2b_256(&leaf));
+    assert!(format!("{:?}", proof).starts_with("Proof { depth: 1, nodes: [ProofNode {"));
+
+    let encoded = proof.encode();
+    assert_eq!(Proof::decode(&encoded)?, proof);
+    assert_eq!(Proof::decode(&encoded)?.encode(), encoded);
+
+    Ok(())
+}
+
+#[test]
+fn proof_decode_errors() -> Result<(), AnyErr> {
+    let key1 = Key::new([0x00; 32]);
+    let key2 = Key::new([0xff; 32]);
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&key1, b"hello")?;
+    tx.insert(&key2, b"world")?;
+    let encoded = tx.prove(&key2)?.encode();
+
+    assert_matches!(
+        Proof::decode(&encoded[..encoded.len() - 1]),
+        Err(DecodeError::UnexpectedEnd)
+    );
+    let mut trailing = encoded.clone();
+    trailing.push(0);
+    assert_matches!(Proof::decode(&trailing), Err(DecodeError::TrailingBytes(1)));
+    // The bitmap of a single node has seven bits of padding.
+    let mut padded = encoded.clone();
+    padded[4] |= 0x01;
+    assert_matches!(Proof::decode(&padded), Err(DecodeError::NonCanonical));
+    assert_matches!(
+        Proof::decode(b"bogus"),
+        Err(DecodeError::InvalidDepth(0x2f62))
+    );
+
+    let bogus = Proof::new_unchecked(b"bogus".to_vec());
+    assert!(bogus.is_malformed());
+    assert_eq!(
+        bogus.decode_error(),
+        Some(&DecodeError::InvalidDepth(0x2f62))
+    );
+    assert_eq!(bogus.depth(), None);
+    assert_eq!(bogus.proof_type(), None);
+    assert_eq!(format!("{:?}", bogus), "Proof::Malformed(626f677573)");
+    assert_eq!(bogus.into_inner(), b"bogus".to_vec());
+
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_roundtrip() -> Result<(), AnyErr> {
+    let key = Key::new([1; 32]);
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&key, b"hello")?;
+    let root = tx.root();
+    let proof = tx.prove(&key)?;
+
+    // Human-readable formats use hex.
+    let json = serde_json::to_string(&root)?;
+    assert_eq!(json, format!("\"{}\"", root));
+    assert_eq!(serde_json::from_str::<Root>(&json)?, root);
+    assert_eq!(serde_json::to_string(&key)?, format!("\"{}\"", key));
+    assert_eq!(
+        serde_json::from_str::<Key>(&serde_json::to_string(&key)?)?,
+        key
+    );
+    let json = serde_json::to_string(&proof)?;
+    assert_eq!(serde_json::from_str::<Proof>(&json)?, proof);
+
+    // Binary formats use raw bytes.
+    let bin = bincode::serialize(&root)?;
+    assert_eq!(&bin[8..], root.as_bytes());
+    assert_eq!(bincode::deserialize::<Root>(&bin)?, root);
+    assert_eq!(
+        bincode::deserialize::<Key>(&bincode::serialize(&key)?)?,
+        key
+    );
+    let bin = bincode::serialize(&proof)?;
+    assert_eq!(&bin[8..], &proof.encode()[..]);
+    assert_eq!(bincode::deserialize::<Proof>(&bin)?, proof);
+
+    let err = VerifyError::HashMismatch;
+    assert_eq!(
+        serde_json::from_str::<VerifyError>(&serde_json::to_string(&err)?)?,
+        err
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_rejects_malformed() {
+    assert!(serde_json::from_str::<Root>("\"0102\"").is_err());
+    assert!(serde_json::from_str::<Key>("\"zz\"").is_err());
+    // "bogus" in hex.
+    assert!(serde_json::from_str::<Proof>("\"626f677573\"").is_err());
+    let bin = bincode::serialize(&b"bogus"[..]).unwrap();
+    assert!(bincode::deserialize::<Proof>(&bin).is_err());
+}
+
+/// Checks that the pure-Rust verifier agrees with liburkel on `proof`.
+#[cfg(feature = "verify")]
+fn assert_verifiers_agree(proof: &Proof, key: &Key, root: Root) {
+    assert_eq!(
+        proof.verify_pure(key, root),
+        proof.verify_native(key, root),
+        "proof {:02x?} for {} at {}",
+        proof.encode(),
+        key,
+        root,
+    );
+}
+
+#[cfg(feature = "verify")]
+#[test]
+fn pure_verifier_matches_native() -> Result<(), AnyErr> {
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    let keys = (0..100u32)
+        .map(|i| Key::hash_of(&i.to_le_bytes()))
+        .collect::<Vec<_>>();
+    // Covers empty trees, then trees of growing size. Absent keys give deadend, short and
+    // collision proofs.
+    let mut roots = vec![tx.root()];
+    for (i, key) in keys.iter().enumerate().step_by(2) {
+        tx.insert(key, &i.to_le_bytes())?;
+        roots.push(tx.commit()?.root);
+    }
+
+    for &root in &roots {
+        for key in &keys {
+            let proof = tmp_db.db.prove(key, root)?;
+            assert_verifiers_agree(&proof, key, root);
+            assert_verifiers_agree(&proof, &keys[0], root);
+            assert_verifiers_agree(&proof, key, roots[1]);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "verify")]
+#[test]
+fn pure_verifier_matches_native_on_corrupted_proofs() -> Result<(), AnyErr> {
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    let keys = (0..16u32)
+        .map(|i| Key::hash_of(&i.to_le_bytes()))
+        .collect::<Vec<_>>();
+    for key in &keys[..8] {
+        tx.insert(key, b"value")?;
+    }
+    let root = tx.commit()?.root;
+
+    for key in &keys {
+        let raw = tmp_db.db.prove(key, root)?.into_inner();
+        for len in 0..raw.len() {
+            assert_verifiers_agree(&Proof::new_unchecked(raw[..len].to_vec()), key, root);
+        }
+        for i in 0..raw.len() {
+            for &mask in &[0x01, 0x80, 0xff] {
+                let mut corrupted = raw.clone();
+                corrupted[i] ^= mask;
+                assert_verifiers_agree(&Proof::new_unchecked(corrupted), key, root);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn fuzz_1() -> Result<(), AnyErr> {
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(
+        &Key::new(hex!(
+            "0000000000000000000000000000000000000000000000000000000000000001"
+        )),
+        &[],
+    )?;
+    tx.commit()?;
+    tx.commit()?;
+
+    assert_matches!(
+        tx.remove(&Key::new(hex!(
+            "0000000000000000000000000000000000000000000000000000000000000000"
+        ))),
+        Err(crate::Error::NotFound)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn snapshot_bogus_root() -> Result<(), AnyErr> {
+    let tmp_db = TmpDatabase::new()?;
+    assert_matches!(
+        tmp_db.db.snapshot(Root::new([3; 32])),
+        Err(crate::Error::NotFound)
+    );
+    Ok(())
+}
+
+#[test]
+fn snapshot_reads_historical_root() -> Result<(), AnyErr> {
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&key1, b"hello")?;
+    tx.commit()?;
+    let root1 = tx.root();
+    tx.insert(&key2, b"world")?;
+    tx.commit()?;
+    let root2 = tx.root();
+
+    let snap1 = tmp_db.db.snapshot(root1)?;
+    let snap2 = tmp_db.db.snapshot(root2)?;
+    assert_eq!(snap1.root(), root1);
+
+    assert!(snap1.has(&key1)?);
+    assert!(!snap1.has(&key2)?);
+    assert_eq!(snap1.get(&key1)?, Some(b"hello".to_vec()));
+    assert_eq!(snap1.get(&key2)?, None);
+    assert_eq!(snap2.get(&key2)?, Some(b"world".to_vec()));
+
+    let proof = snap1.prove(&key2)?;
+    assert_eq!(proof.verify(&key2, root1)?, None);
+
+    let mut iter = snap1.iter()?;
+    assert_eq!(iter.next().transpose()?, Some((key1, b"hello".to_vec())));
+    assert_eq!(iter.next().transpose()?, None);
+
+    Ok(())
+}
+
+#[test]
+fn db_insert_remove_reopen() -> Result<(), AnyErr> {
+    let mut tmp_db = TmpDatabase::new()?;
+    tmp_db.db.insert(&Key::new([1; 32]), b"hello")?;
+    assert_eq!(
+        tmp_db.db.root(),
+        Root::new(hex!(
+            "58f8fd75fe4ebe990b2e84e497932ae7c4e29c841035a6fa9b6879d44902d73a"
+        ))
+    );
+
+    tmp_db = tmp_db.reopen()?;
+    assert_eq!(
+        tmp_db.db.root(),
+        Root::new(hex!(
+            "58f8fd75fe4ebe990b2e84e497932ae7c4e29c841035a6fa9b6879d44902d73a"
+        ))
+    );
+    assert!(tmp_db.db.new_tx()?.has(&Key::new([1; 32]))?);
+
+    tmp_db.db.remove(&Key::new([1; 32]))?;
+    assert_eq!(tmp_db.db.root(), Root::EMPTY);
+
+    tmp_db = tmp_db.reopen()?;
+    assert_eq!(tmp_db.db.root(), Root::EMPTY);
+    assert!(!tmp_db.db.new_tx()?.has(&Key::new([1; 32]))?);
+
+    Ok(())
+}
+
+#[test]
+fn db_insert_value_too_large() -> Result<(), AnyErr> {
+    let mut tmp_db = TmpDatabase::new()?;
+    assert_matches!(
+        tmp_db.db.insert(&Key::new([1; 32]), &[0u8; 1025]),
+        Err(crate::Error::ValueTooLarge)
+    );
+    assert_eq!(tmp_db.db.root(), Root::EMPTY);
+    Ok(())
+}
+
+#[test]
+fn db_set_head_rewinds() -> Result<(), AnyErr> {
+    let mut tmp_db = TmpDatabase::new()?;
+    let (root1, root2) = {
+        let mut tx = tmp_db.db.new_tx()?;
+        tx.insert(&Key::new([1; 32]), b"hello")?;
+        tx.commit()?;
+        let root1 = tx.root();
+        tx.insert(&Key::new([2; 32]), b"world")?;
+        tx.commit()?;
+        (root1, tx.root())
+    };
+    assert_eq!(tmp_db.db.root(), root2);
+
+    tmp_db.db.set_head(root1)?;
+    assert_eq!(tmp_db.db.root(), root1);
+
+    tmp_db = tmp_db.reopen()?;
+    assert_eq!(tmp_db.db.root(), root1);
+    let tx = tmp_db.db.new_tx()?;
+    assert!(tx.has(&Key::new([1; 32]))?);
+    assert!(!tx.has(&Key::new([2; 32]))?);
+    drop(tx);
+
+    // The rewound-from root is still available.
+    tmp_db.db.set_head(root2)?;
+    assert_eq!(tmp_db.db.root(), root2);
+
+    assert_matches!(
+        tmp_db.db.set_head(Root::new([3; 32])),
+        Err(crate::Error::NotFound)
+    );
+    assert_eq!(tmp_db.db.root(), root2);
+
+    Ok(())
+}
+
+#[test]
+fn root_hex_roundtrip() {
+    let hex = "58f8fd75fe4ebe990b2e84e497932ae7c4e29c841035a6fa9b6879d44902d73a";
+    let root: Root = hex.parse().unwrap();
+    assert_eq!(
+        root,
+        Root::new(hex!(
+            "58f8fd75fe4ebe990b2e84e497932ae7c4e29c841035a6fa9b6879d44902d73a"
+        ))
+    );
+    assert_eq!(root.to_string(), hex);
+    assert_eq!(format!("{:x}", root), hex);
+    assert_eq!(format!("{:#x}", root), format!("0x{}", hex));
+    assert_eq!(hex.to_uppercase().parse::<Root>().unwrap(), root);
+    assert_eq!(root.as_ref(), &root.to_bytes()[..]);
+
+    assert_eq!(Root::EMPTY.to_string(), "0".repeat(64));
+    assert_eq!(Root::default(), Root::EMPTY);
+}
+
+#[test]
+fn root_parse_errors() {
+    assert_eq!("".parse::<Root>(), Err(ParseHexError::InvalidLength(0)));
+    assert_eq!(
+        "00".repeat(33).parse::<Root>(),
+        Err(ParseHexError::InvalidLength(66))
+    );
+    let mut bad = "0".repeat(64);
+    bad.replace_range(5..6, "g");
+    assert_eq!(bad.parse::<Root>(), Err(ParseHexError::InvalidCharacter(5)));
+}
+
+#[test]
+fn concurrent_transactions() -> Result<(), AnyErr> {
+    const THREADS: u8 = 8;
+    const KEYS_PER_THREAD: u8 = 64;
+
+    fn assert_send<T: Send>() {}
+    fn assert_sync<T: Sync>() {}
+    assert_send::<Database>();
+    assert_sync::<Database>();
+    assert_send::<crate::Transaction>();
+
+    fn key(thread: u8, i: u8) -> Key {
+        let mut key = [thread; 32];
+        key[31] = i;
+        Key::new(key)
+    }
+
+    fn fill(tx: &mut crate::Transaction, thread: u8) -> Result<(), crate::Error> {
+        for i in 0..KEYS_PER_THREAD {
+            tx.insert(&key(thread, i), &[thread, i])?;
+            if i % 16 == 15 {
+                tx.commit()?;
+            }
+        }
+        Ok(())
+    }
+
+    let tmp_db = TmpDatabase::new()?;
+    let db = &tmp_db.db;
+
+    // Every thread commits into the shared database while readers look at the other roots.
+    // Half of the transactions are created on this thread and moved into the workers.
+    let roots = std::thread::scope(|s| {
+        let handles = (0..THREADS)
+            .map(|thread| {
+                let moved_tx = if thread % 2 == 0 {
+                    Some(db.new_tx_at(Root::EMPTY))
+                } else {
+                    None
+                };
+                s.spawn(move || -> Result<Root, crate::Error> {
+                    let mut tx = match moved_tx {
+                        Some(tx) => tx?,
+                        None => db.new_tx_at(Root::EMPTY)?,
+                    };
+                    fill(&mut tx, thread)?;
+                    let snapshot = db.snapshot(tx.root())?;
+                    for i in 0..KEYS_PER_THREAD {
+                        assert_eq!(snapshot.get(&key(thread, i))?, Some(vec![thread, i]));
+                        assert!(!snapshot.has(&key(thread.wrapping_add(1), i))?);
+                    }
+                    Ok(tx.root())
+                })
+            })
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Result<Vec<_>, _>>()
+    })?;
+
+    // The roots must be the same as if the work had been done sequentially.
+    for (thread, root) in (0..THREADS).zip(roots) {
+        let mut tx = db.new_tx_at(Root::EMPTY)?;
+        fill(&mut tx, thread)?;
+        assert_eq!(tx.root(), root);
+
+        let mut count = 0;
+        for leaf in db.iter(root)? {
+            let (k, v) = leaf?;
+            assert_eq!(k, key(thread, count));
+            assert_eq!(v, vec![thread, count]);
+            count += 1;
+        }
+        assert_eq!(count, KEYS_PER_THREAD);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn errno_mapping() {
+    use crate::error::Errno;
+    use crate::Error;
+    use std::path::Path;
+
+    let path = Path::new("/var/lib/urkel");
+    let codes = [
+        urkel_sys::URKEL_ENOTFOUND,
+        urkel_sys::URKEL_ECORRUPTION,
+        urkel_sys::URKEL_ENOUPDATE,
+        urkel_sys::URKEL_EBADWRITE,
+        urkel_sys::URKEL_EBADOPEN,
+        urkel_sys::URKEL_EINVAL,
+        urkel_sys::URKEL_EHASHMISMATCH,
+    ];
+    for &code in &codes {
+        let err = Errno(code).into_error("commit", path);
+        assert_eq!(err.errno(), Some(code));
+        match err {
+            Error::NotFound => assert_eq!(code, urkel_sys::URKEL_ENOTFOUND),
+            Error::Corruption { op, ref path } | Error::BadWrite { op, ref path } => {
+                assert_eq!(op, "commit");
+                assert_eq!(path, Path::new("/var/lib/urkel"));
+                assert!(err.to_string().contains("/var/lib/urkel"));
+            }
+            Error::NoUpdate { op } | Error::InvalidArgument { op } => assert_eq!(op, "commit"),
+            Error::BadOpen { op, ref path } => {
+                assert_eq!(op, "commit");
+                assert_eq!(path, Path::new("/var/lib/urkel"));
+            }
+            Error::Unknown { op, errno } => {
+                assert_eq!(op, "commit");
+                assert_eq!(errno, urkel_sys::URKEL_EHASHMISMATCH);
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    assert_eq!(Error::ValueTooLarge.errno(), None);
+}
+
+#[test]
+fn iter_std_iterator() -> Result<(), AnyErr> {
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    for i in 0..10 {
+        tx.insert(&Key::new([i; 32]), &[i])?;
+    }
+    tx.commit()?;
+    let root = tx.root();
+
+    let all = tx.iter()?.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(
+        all,
+        (0..10)
+            .map(|i| (Key::new([i; 32]), vec![i]))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(tmp_db.db.iter(root)?.collect::<Result<Vec<_>, _>>()?, all);
+
+    let mut count = 0;
+    for leaf in tx.iter()? {
+        let (key, value) = leaf?;
+        assert_eq!(key, Key::new([count; 32]));
+        assert_eq!(value, vec![count]);
+        count += 1;
+    }
+    assert_eq!(count, 10);
+
+    Ok(())
+}
+
+#[test]
+fn iter_seek_and_range() -> Result<(), AnyErr> {
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    for i in 0..10 {
+        tx.insert(&Key::new([i * 2; 32]), &[i * 2])?;
+    }
+    tx.commit()?;
+
+    fn values(iter: crate::Iter) -> Result<Vec<u8>, crate::Error> {
+        iter.map(|leaf| leaf.map(|(_, value)| value[0])).collect()
+    }
+
+    // Seeking to a missing key starts at the next one.
+    assert_eq!(
+        values(tx.iter()?.seek(&Key::new([5; 32])))?,
+        vec![6, 8, 10, 12, 14, 16, 18]
+    );
+    assert_eq!(
+        values(tx.iter()?.seek(&Key::new([6; 32])))?,
+        vec![6, 8, 10, 12, 14, 16, 18]
+    );
+    assert_eq!(
+        values(tx.iter()?.seek(&Key::new([19; 32])))?,
+        Vec::<u8>::new()
+    );
+
+    assert_eq!(
+        values(tx.iter()?.range(Key::new([4; 32])..Key::new([10; 32])))?,
+        vec![4, 6, 8]
+    );
+    assert_eq!(
+        values(tx.iter()?.range(Key::new([4; 32])..=Key::new([10; 32])))?,
+        vec![4, 6, 8, 10]
+    );
+    assert_eq!(values(tx.iter()?.range(..Key::new([3; 32])))?, vec![0, 2]);
+    assert_eq!(
+        values(tmp_db.db.iter(tx.root())?.range(Key::new([15; 32])..))?,
+        vec![16, 18]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn iter_keys_and_rev() -> Result<(), AnyErr> {
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    for i in 0..5 {
+        tx.insert(&Key::new([i; 32]), &[i; 1024])?;
+    }
+
+    let keys = tx.iter()?.keys().collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(keys, (0..5).map(|i| Key::new([i; 32])).collect::<Vec<_>>());
+
+    let rev_keys = tx.iter()?.keys().rev().collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(
+        rev_keys,
+        (0..5).rev().map(|i| Key::new([i; 32])).collect::<Vec<_>>()
+    );
+
+    let rev = tx
+        .iter()?
+        .range(Key::new([1; 32])..Key::new([4; 32]))
+        .rev()
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(
+        rev,
+        (1..4)
+            .rev()
+            .map(|i| (Key::new([i; 32]), vec![i; 1024]))
+            .collect::<Vec<_>>()
+    );
+
+    // Both ends can be consumed from the same iterator.
+    let mut iter = tx.iter()?.keys();
+    assert_eq!(iter.next().transpose()?, Some(Key::new([0; 32])));
+    assert_eq!(iter.next_back().transpose()?, Some(Key::new([4; 32])));
+    assert_eq!(iter.next().transpose()?, Some(Key::new([1; 32])));
+    assert_eq!(iter.next_back().transpose()?, Some(Key::new([3; 32])));
+    assert_eq!(iter.next().transpose()?, Some(Key::new([2; 32])));
+    assert_eq!(iter.next().transpose()?, None);
+    assert_eq!(iter.next_back().transpose()?, None);
+
+    Ok(())
+}
+
+#[test]
+fn commit_report() -> Result<(), AnyErr> {
+    use crate::ChangeKind;
+
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
+    let key3 = Key::new([3; 32]);
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    assert!(!tx.is_dirty());
+
+    tx.insert(&key2, b"two")?;
+    tx.insert(&key1, b"one")?;
+    assert!(tx.is_dirty());
+    let report = tx.commit()?;
+    assert_eq!(report.prev_root, Root::EMPTY);
+    assert_eq!(report.root, tx.root());
+    assert_eq!(report.root, tmp_db.db.root());
+    assert_eq!(report.inserted, vec![key1, key2]);
+    assert!(report.updated.is_empty());
+    assert!(report.removed.is_empty());
+    assert!(!tx.is_dirty());
+    assert!(tx.pending_changes()?.is_empty());
+
+    let root1 = report.root;
+    tx.insert(&key1, b"uno")?;
+    tx.remove(&key2)?;
+    tx.insert(&key3, b"three")?;
+    assert_eq!(
+        tx.pending_changes()?,
+        vec![
+            (key1, ChangeKind::Updated),
+            (key2, ChangeKind::Removed),
+            (key3, ChangeKind::Inserted),
+        ]
+    );
+    let report = tx.commit()?;
+    assert_eq!(report.prev_root, root1);
+    assert_eq!(report.inserted, vec![key3]);
+    assert_eq!(report.updated, vec![key1]);
+    assert_eq!(report.removed, vec![key2]);
+
+    // An empty commit reports nothing.
+    let report = tx.commit()?;
+    assert_eq!(report.prev_root, report.root);
+    assert!(report.inserted.is_empty() && report.updated.is_empty() && report.removed.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn pending_changes_cancel_out() -> Result<(), AnyErr> {
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&key1, b"one")?;
+    tx.commit()?;
+
+    tx.insert(&key2, b"two")?;
+    tx.remove(&key2)?;
+    tx.insert(&key1, b"uno")?;
+    tx.insert(&key1, b"one")?;
+    assert!(!tx.is_dirty());
+    assert!(tx.pending_changes()?.is_empty());
+
+    // A failed removal doesn't count as a change either.
+    assert_matches!(tx.remove(&key2), Err(crate::Error::NotFound));
+    assert!(tx.pending_changes()?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn commit_report_after_revert() -> Result<(), AnyErr> {
+    use crate::ChangeKind;
+
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
+    let key3 = Key::new([3; 32]);
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&key1, b"one")?;
+    let root1 = tx.commit()?.root;
+    tx.insert(&key1, b"uno")?;
+    tx.insert(&key2, b"two")?;
+    let root2 = tx.commit()?.root;
+
+    tx.insert(&key3, b"three")?;
+    tx.revert(root1)?;
+    assert_eq!(
+        tx.pending_changes()?,
+        vec![(key1, ChangeKind::Updated), (key2, ChangeKind::Removed)]
+    );
+    let report = tx.commit()?;
+    assert_eq!(report.prev_root, root2);
+    assert_eq!(report.root, root1);
+    assert_eq!(report.updated, vec![key1]);
+    assert_eq!(report.removed, vec![key2]);
+    assert!(report.inserted.is_empty());
+
+    // Writes between reverts, and savepoints taken across them, are accounted for.
+    tx.insert(&key2, b"two")?;
+    tx.revert(root2)?;
+    let savepoint = tx.savepoint();
+    tx.insert(&key3, b"three")?;
+    tx.revert(root1)?;
+    tx.rollback_to(savepoint)?;
+    assert_eq!(
+        tx.pending_changes()?,
+        vec![
+            (key1, ChangeKind::Updated),
+            (key2, ChangeKind::Inserted),
+            (key3, ChangeKind::Inserted)
+        ]
+    );
+    tx.revert(root1)?;
+    assert_eq!(tx.pending_changes()?, vec![]);
+
+    Ok(())
+}
+
+#[test]
+fn savepoint_rollback() -> Result<(), AnyErr> {
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
+    let key3 = Key::new([3; 32]);
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&key1, b"one")?;
+    tx.insert(&key2, b"two")?;
+    tx.commit()?;
+
+    // The first call of the block succeeds.
+    tx.insert(&key1, b"uno")?;
+    let after_first_call = tx.root();
+
+    // The second call reverts: all of its writes are undone.
+    let call = tx.savepoint();
+    tx.insert(&key1, b"eins")?;
+    tx.remove(&key2)?;
+    tx.insert(&key3, b"drei")?;
+    tx.insert(&key3, b"three")?;
+    tx.rollback_to(call)?;
+
+    assert_eq!(tx.root(), after_first_call);
+    assert_eq!(tx.get(&key1)?, Some(b"uno".to_vec()));
+    assert_eq!(tx.get(&key2)?, Some(b"two".to_vec()));
+    assert_eq!(tx.get(&key3)?, None);
+    assert_eq!(
+        tx.pending_changes()?,
+        vec![(key1, crate::ChangeKind::Updated)]
+    );
+
+    // The savepoint can be rolled back to again.
+    tx.remove(&key1)?;
+    tx.rollback_to(call)?;
+    assert_eq!(tx.root(), after_first_call);
+    tx.release(call)?;
+    assert_matches!(tx.rollback_to(call), Err(crate::Error::UnknownSavepoint));
+
+    let report = tx.commit()?;
+    assert_eq!(report.updated, vec![key1]);
+    assert!(report.inserted.is_empty() && report.removed.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn nested_savepoints() -> Result<(), AnyErr> {
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
+    let key3 = Key::new([3; 32]);
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+
+    let outer = tx.savepoint();
+    tx.insert(&key1, b"one")?;
+    let root1 = tx.root();
+
+    let inner = tx.savepoint();
+    tx.insert(&key2, b"two")?;
+    let root2 = tx.root();
+
+    let innermost = tx.savepoint();
+    tx.insert(&key3, b"three")?;
+
+    // Releasing keeps the changes but the outer savepoints can still undo them.
+    tx.release(innermost)?;
+    assert!(tx.has(&key3)?);
+    assert_matches!(tx.release(innermost), Err(crate::Error::UnknownSavepoint));
+
+    tx.rollback_to(inner)?;
+    assert_eq!(tx.root(), root1);
+
+    tx.insert(&key2, b"two")?;
+    assert_eq!(tx.root(), root2);
+    tx.rollback_to(outer)?;
+    assert_eq!(tx.root(), Root::EMPTY);
+    assert_matches!(tx.rollback_to(inner), Err(crate::Error::UnknownSavepoint));
+
+    Ok(())
+}
+
+#[test]
+fn savepoint_across_revert() -> Result<(), AnyErr> {
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&key1, b"one")?;
+    let root1 = tx.commit()?.root;
+
+    tx.insert(&key2, b"two")?;
+    let before = tx.root();
+    let savepoint = tx.savepoint();
+    tx.revert(Root::EMPTY)?;
+    assert!(!tx.has(&key1)?);
+    tx.rollback_to(savepoint)?;
+    assert_eq!(tx.root(), before);
+
+    // Savepoints don't survive a commit.
+    let savepoint = tx.savepoint();
+    tx.commit()?;
+    assert_matches!(
+        tx.rollback_to(savepoint),
+        Err(crate::Error::UnknownSavepoint)
+    );
+    assert_ne!(tx.root(), root1);
+
+    Ok(())
+}
+
+#[test]
+fn tx_clear() -> Result<(), AnyErr> {
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&key1, b"one")?;
+    let root1 = tx.commit()?.root;
+
+    let savepoint = tx.savepoint();
+    tx.insert(&key2, b"two")?;
+    tx.remove(&key1)?;
+    tx.clear()?;
+
+    assert_eq!(tx.root(), root1);
+    assert!(!tx.is_dirty());
+    assert!(tx.pending_changes()?.is_empty());
+    assert!(tx.has(&key1)?);
+    assert!(!tx.has(&key2)?);
+    assert_matches!(
+        tx.rollback_to(savepoint),
+        Err(crate::Error::UnknownSavepoint)
+    );
+
+    // The transaction is still usable afterwards.
+    tx.insert(&key2, b"two")?;
+    assert_eq!(tx.commit()?.inserted, vec![key2]);
+
+    Ok(())
+}
+
+#[test]
+fn write_batch_apply() -> Result<(), AnyErr> {
+    use crate::{BatchOp, WriteBatch};
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut batch = WriteBatch::new();
+    for i in 0..10 {
+        batch.put(&Key::new([i; 32]), vec![i]);
+    }
+    batch.delete(&Key::new([3; 32]));
+    assert_eq!(batch.len(), 11);
+    assert_eq!(batch.ops()[10], BatchOp::Delete(Key::new([3; 32])));
+
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.apply(&batch)?;
+    assert_eq!(tx.iter()?.count(), 9);
+    assert!(!tx.has(&Key::new([3; 32]))?);
+    assert_eq!(tx.get(&Key::new([7; 32]))?, Some(vec![7]));
+
+    // The same batch applied through the database gives the same root.
+    let root = tmp_db.db.write(&batch)?;
+    assert_eq!(root, tx.root());
+    assert_eq!(tmp_db.db.root(), root);
+
+    Ok(())
+}
+
+#[test]
+fn write_batch_is_atomic() -> Result<(), AnyErr> {
+    use crate::WriteBatch;
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    tx.insert(&Key::new([0; 32]), b"existing")?;
+    let before = tx.root();
+
+    // An oversized value is caught before anything is written.
+    let mut batch = WriteBatch::new();
+    for i in 0..600u16 {
+        let value = if i == 499 { vec![0; 1025] } else { vec![1] };
+        batch.put(&Key::hash_of(&i.to_le_bytes()), value);
+    }
+    assert_matches!(tx.apply(&batch), Err(crate::Error::ValueTooLarge));
+    assert_eq!(tx.root(), before);
+
+    // A failure in the middle undoes the operations before it.
+    let savepoint = tx.savepoint();
+    let mut batch = WriteBatch::new();
+    batch
+        .put(&Key::new([1; 32]), b"one")
+        .delete(&Key::new([0; 32]))
+        .delete(&Key::new([2; 32]))
+        .put(&Key::new([3; 32]), b"three");
+    assert_matches!(tx.apply(&batch), Err(crate::Error::NotFound));
+    assert_eq!(tx.root(), before);
+    assert_eq!(tx.get(&Key::new([0; 32]))?, Some(b"existing".to_vec()));
+
+    // Savepoints taken outside of the batch are untouched.
+    tx.insert(&Key::new([4; 32]), b"four")?;
+    tx.rollback_to(savepoint)?;
+    assert_eq!(tx.root(), before);
+
+    // Nothing is committed when `Database::write` fails.
+    assert_matches!(tmp_db.db.write(&batch), Err(crate::Error::NotFound));
+    assert_eq!(tmp_db.db.root(), Root::EMPTY);
+
+    Ok(())
+}
+
+#[test]
+fn diff_roots() -> Result<(), AnyErr> {
+    use crate::Change;
+
+    let tmp_db = TmpDatabase::new()?;
+    let mut tx = tmp_db.db.new_tx()?;
+    for i in 0..8 {
+        tx.insert(&Key::new([i; 32]), &[i])?;
+    }
+    let from = tx.commit()?.root;
+
+    tx.remove(&Key::new([0; 32]))?;
+    tx.insert(&Key::new([2; 32]), b"two")?;
+    tx.insert(&Key::new([5; 32]), &[5])?;
+    tx.remove(&Key::new([6; 32]))?;
+    tx.insert(&Key::new([9; 32]), b"nine")?;
+    let to = tx.commit()?.root;
+
+    let changes = tmp_db.db.diff(from, to)?.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(
+        changes,
+        vec![
+            Change::Removed(Key::new([0; 32]), vec![0]),
+            Change::Changed(Key::new([2; 32]), vec![2], b"two".to_vec()),
+            Change::Removed(Key::new([6; 32]), vec![6]),
+            Change::Added(Key::new([9; 32]), b"nine".to_vec()),
+        ]
+    );
+
+    let reversed = tmp_db.db.diff(to, from)?.collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(
+        reversed,
+        vec![
+            Change::Added(Key::new([0; 32]), vec![0]),
+            Change::Changed(Key::new([2; 32]), b"two".to_vec(), vec![2]),
+            Change::Added(Key::new([6; 32]), vec![6]),
+            Change::Removed(Key::new([9; 32]), b"nine".to_vec()),
+        ]
+    );
+    assert_eq!(reversed[3].key(), &Key::new([9; 32]));
+
+    assert_eq!(tmp_db.db.diff(to, to)?.count(), 0);
+    assert_eq!(tmp_db.db.diff(Root::EMPTY, from)?.count(), 8);
+    assert!(matches!(
+        tmp_db.db.diff(from, Root::new([3; 32])),
+        Err(crate::Error::NotFound)
+    ));
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn async_transaction() -> Result<(), AnyErr> {
+    use futures_util::StreamExt;
+
+    let key1 = Key::new([1; 32]);
+    let key2 = Key::new([2; 32]);
+
+    let prefix_dir = tempdir()?;
+    let db = crate::AsyncDatabase::open(prefix_dir.path()).await?;
+    let mut tx = db.new_tx().await?;
+    tx.insert(&key1, &b"hello"[..]).await?;
+    tx.insert(&key2, &b"world"[..]).await?;
+    assert_eq!(tx.get(&key1).await?, Some(b"hello".to_vec()));
+
+    let leaves = tx.iter().collect::<Vec<_>>().await;
+    assert_eq!(leaves.len(), 2);
+    assert_eq!(leaves[1].as_ref().unwrap(), &(key2, b"world".to_vec()));
+
+    let root = tx.commit().await?.root;
+    assert_eq!(db.root(), root);
+    assert_eq!(db.get(&key2, root).await?, Some(b"world".to_vec()));
+    let proof = db.prove(&key1, root).await?;
+    assert_eq!(proof.verify(&key1, root)?, Some(b"hello".to_vec()));
+
+    let keys = db
+        .iter(root)
+        .map(|leaf| leaf.map(|(key, _)| key))
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(
+        keys.into_iter().collect::<Result<Vec<_>, _>>()?,
+        vec![key1, key2]
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "async")]
+#[tokio::test]
+async fn async_cancellation() -> Result<(), AnyErr> {
+    use futures_util::{FutureExt, StreamExt};
+    use std::sync::{Arc, Barrier};
+
+    let key = Key::new([1; 32]);
+
+    let prefix_dir = tempdir()?;
+    let db = crate::AsyncDatabase::open(prefix_dir.path()).await?;
+    let mut tx = db.new_tx().await?;
+    for i in 0..200u32 {
+        tx.insert(&Key::hash_of(&i.to_le_bytes()), i.to_le_bytes().to_vec())
+            .await?;
+    }
+
+    // A call dropped after its first poll still runs to completion. The worker is held up so
+    // that the call can't be done by then.
+    let barrier = Arc::new(Barrier::new(2));
+    let worker_barrier = barrier.clone();
+    let blocked = tx.call(move |_| {
+        worker_barrier.wait();
+    });
+    assert!(blocked.now_or_never().is_none());
+    assert!(tx.insert(&key, &b"hello"[..]).now_or_never().is_none());
+    barrier.wait();
+    assert_eq!(tx.get(&key).await?, Some(b"hello".to_vec()));
+
+    // A dropped stream stops the iteration and frees the worker.
+    let mut leaves = tx.iter();
+    leaves.next().await.unwrap()?;
+    drop(leaves);
+    let root = tx.commit().await?.root;
+
+    // Dropping the transaction destroys it on its worker, the database stays usable.
+    drop(tx);
+    let mut tx = db.new_tx_at(root).await?;
+    tx.remove(&key).await?;
+    tx.commit().await?;
+    assert_eq!(db.get(&key, root).await?, Some(b"hello".to_vec()));
 
     Ok(())
 }
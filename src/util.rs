@@ -1,8 +1,12 @@
-use crate::Error;
-use std::ffi::CString;
-use std::path::Path;
+use crate::error::ParseHexError;
+#[cfg(feature = "serde")]
+use alloc::vec::Vec;
+use core::fmt;
+#[cfg(feature = "native")]
+use {crate::Error, std::ffi::CString, std::path::Path};
 
 /// Convert a `Path` into a `CString`.
+#[cfg(feature = "native")]
 pub(crate) fn path_into_c_string(path: &Path) -> Result<CString, Error> {
     let os_string = path.as_os_str().to_os_string();
 
@@ -16,6 +20,7 @@ pub(crate) fn path_into_c_string(path: &Path) -> Result<CString, Error> {
     }
 }
 
+#[cfg(feature = "native")]
 pub fn blake2b_256(data: &[u8]) -> [u8; 32] {
     let mut out = [0; 32];
     unsafe {
@@ -23,3 +28,76 @@ pub fn blake2b_256(data: &[u8]) -> [u8; 32] {
     }
     out
 }
+
+#[cfg(all(feature = "verify", not(feature = "native")))]
+pub fn blake2b_256(data: &[u8]) -> [u8; 32] {
+    let mut out = [0; 32];
+    out.copy_from_slice(blake2_rfc::blake2b::blake2b(32, &[], data).as_bytes());
+    out
+}
+
+/// Writes `bytes` to the formatter as lowercase hex, honoring the `#` flag for a `0x` prefix.
+pub(crate) fn write_hex(f: &mut fmt::Formatter, bytes: &[u8]) -> fmt::Result {
+    if f.alternate() {
+        f.write_str("0x")?;
+    }
+    for byte in bytes {
+        write!(f, "{:02x}", byte)?;
+    }
+    Ok(())
+}
+
+/// Formats bytes as lowercase hex.
+pub(crate) struct Hex<'a>(pub(crate) &'a [u8]);
+
+impl fmt::Debug for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_hex(f, self.0)
+    }
+}
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_hex(f, self.0)
+    }
+}
+
+/// Parses exactly 64 hexadecimal characters into 32 bytes.
+pub(crate) fn parse_hex_32(s: &str) -> Result<[u8; 32], ParseHexError> {
+    if s.len() != 64 {
+        return Err(ParseHexError::InvalidLength(s.len()));
+    }
+    let mut out = [0; 32];
+    decode_hex(s.as_bytes(), &mut out)?;
+    Ok(out)
+}
+
+/// Parses an even number of hexadecimal characters into bytes.
+#[cfg(feature = "serde")]
+pub(crate) fn parse_hex(s: &str) -> Result<Vec<u8>, ParseHexError> {
+    if !s.len().is_multiple_of(2) {
+        return Err(ParseHexError::OddLength(s.len()));
+    }
+    let mut out = alloc::vec![0; s.len() / 2];
+    decode_hex(s.as_bytes(), &mut out)?;
+    Ok(out)
+}
+
+/// Decodes `s`, which must be twice as long as `out`, into `out`.
+fn decode_hex(s: &[u8], out: &mut [u8]) -> Result<(), ParseHexError> {
+    fn nibble(c: u8) -> Option<u8> {
+        match c {
+            b'0'..=b'9' => Some(c - b'0'),
+            b'a'..=b'f' => Some(c - b'a' + 10),
+            b'A'..=b'F' => Some(c - b'A' + 10),
+            _ => None,
+        }
+    }
+
+    for (i, pair) in s.chunks(2).enumerate() {
+        let hi = nibble(pair[0]).ok_or(ParseHexError::InvalidCharacter(i * 2))?;
+        let lo = nibble(pair[1]).ok_or(ParseHexError::InvalidCharacter(i * 2 + 1))?;
+        out[i] = hi << 4 | lo;
+    }
+    Ok(())
+}
@@ -0,0 +1,8 @@
+use urkel::Database;
+
+fn main() {
+    let db = Database::open("/tmp/urkel-ui").unwrap();
+    let mut iter = db.iter(db.root()).unwrap();
+    drop(db);
+    let _ = iter.next();
+}
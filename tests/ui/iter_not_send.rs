@@ -0,0 +1,9 @@
+use urkel::Database;
+
+fn main() {
+    let db = Database::open("/tmp/urkel-ui").unwrap();
+    let mut iter = db.iter(db.root()).unwrap();
+    std::thread::scope(|s| {
+        s.spawn(move || iter.next());
+    });
+}
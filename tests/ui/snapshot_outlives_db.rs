@@ -0,0 +1,8 @@
+use urkel::Database;
+
+fn main() {
+    let db = Database::open("/tmp/urkel-ui").unwrap();
+    let snapshot = db.snapshot(db.root()).unwrap();
+    drop(db);
+    let _ = snapshot.get(&Default::default());
+}
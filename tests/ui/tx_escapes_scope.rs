@@ -0,0 +1,10 @@
+use urkel::{Database, Transaction};
+
+fn open_tx() -> Transaction<'static> {
+    let db = Database::open("/tmp/urkel-ui").unwrap();
+    db.new_tx().unwrap()
+}
+
+fn main() {
+    let _ = open_tx();
+}
@@ -0,0 +1,9 @@
+use urkel::Database;
+
+fn main() {
+    let db = Database::open("/tmp/urkel-ui").unwrap();
+    let mut tx = db.new_tx().unwrap();
+    let mut iter = tx.iter().unwrap();
+    drop(tx);
+    let _ = iter.next();
+}
@@ -0,0 +1,9 @@
+use urkel::Database;
+
+fn main() {
+    let db = Database::open("/tmp/urkel-ui").unwrap();
+    let tx = db.new_tx().unwrap();
+    std::thread::scope(|s| {
+        s.spawn(|| tx.root());
+    });
+}
@@ -0,0 +1,8 @@
+use urkel::Database;
+
+fn main() {
+    let db = Database::open("/tmp/urkel-ui").unwrap();
+    let mut tx = db.new_tx().unwrap();
+    drop(db);
+    tx.commit().unwrap();
+}